@@ -0,0 +1,889 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+/// Address the assembled program is loaded to.
+const ROM_START: usize = 0x200;
+/// Macros expanding macros deeper than this are assumed to expand themselves forever.
+const MAX_MACRO_DEPTH: usize = 64;
+
+/// Result of assembling a program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    /// Bytes to load at 0x200.
+    pub rom: Vec<u8>,
+    /// Address of every label.
+    pub symbols: BTreeMap<String, u16>,
+    /// Source line of every instruction, in the order they were emitted.
+    pub lines: Vec<(u16, usize)>,
+}
+
+impl Program {
+    /// Formats the symbol map: `label <name> <address>` and `line <line> <address>` lines.
+    pub fn symbol_map(&self) -> String {
+        let mut map = String::new();
+        let mut symbols: Vec<(&String, &u16)> = self.symbols.iter().collect();
+        symbols.sort_by_key(|&(_, address)| *address);
+        for (name, address) in symbols {
+            map += &format!("label {} {:03X}\n", name, address);
+        }
+        for &(address, line) in self.lines.iter() {
+            map += &format!("line {} {:03X}\n", line, address);
+        }
+        map
+    }
+}
+
+/// Error raised while assembling, `line` is 1-based.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for AsmError {}
+
+/// Assembles a program written in the Octo assembly language.
+///
+/// Supported are labels, `:const`, `:alias`, `:macro`, `:org`, `:byte`, `:call`,
+/// all CHIP-8, SUPER-CHIP and XO-CHIP statements and the structured
+/// `if ... then`, `if ... begin ... else ... end` and `loop ... while ... again` forms.
+/// Like Octo, a program with a `main` label that is not its first statement
+/// starts with `jump main` at 0x200.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut assembler = Assembler::new(source);
+    assembler.run()?;
+    assembler.finish()
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    //number of macro expansions the token came from, 0 in the source
+    depth: usize,
+}
+
+//how a forward reference is patched in once the label is known
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Patch {
+    //low 12 bits of the opcode at the position
+    Address,
+    //the 16 bit word at the position, F000 NNNN
+    Long,
+}
+
+struct Fixup {
+    position: usize,
+    patch: Patch,
+    label: String,
+    line: usize,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+//an open structured statement, closed by `end` or `again`
+enum Block {
+    If { else_jump: usize, end_jump: Option<usize> },
+    Loop { start: u16, breaks: Vec<usize> },
+}
+
+//a condition of `if` or `while`, compiled to the opcode skipping the next instruction if it is false
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Condition {
+    EqualByte(u8, u8),
+    NotEqualByte(u8, u8),
+    Equal(u8, u8),
+    NotEqual(u8, u8),
+    Pressed(u8),
+    Released(u8),
+}
+
+impl Condition {
+    fn negate(self) -> Condition {
+        match self {
+            Condition::EqualByte(x, nn) => Condition::NotEqualByte(x, nn),
+            Condition::NotEqualByte(x, nn) => Condition::EqualByte(x, nn),
+            Condition::Equal(x, y) => Condition::NotEqual(x, y),
+            Condition::NotEqual(x, y) => Condition::Equal(x, y),
+            Condition::Pressed(x) => Condition::Released(x),
+            Condition::Released(x) => Condition::Pressed(x),
+        }
+    }
+
+    //opcode skipping the next instruction unless the condition holds
+    fn skip_unless(self) -> u16 {
+        match self {
+            Condition::EqualByte(x, nn) => 0x4000 | xy(x, 0) | nn as u16,
+            Condition::NotEqualByte(x, nn) => 0x3000 | xy(x, 0) | nn as u16,
+            Condition::Equal(x, y) => 0x9000 | xy(x, y),
+            Condition::NotEqual(x, y) => 0x5000 | xy(x, y),
+            Condition::Pressed(x) => 0xE0A1 | xy(x, 0),
+            Condition::Released(x) => 0xE09E | xy(x, 0),
+        }
+    }
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    position: usize,
+    rom: Vec<u8>,
+    //offset of the next byte in `rom`
+    here: usize,
+    labels: BTreeMap<String, u16>,
+    constants: BTreeMap<String, i32>,
+    aliases: BTreeMap<String, u8>,
+    macros: BTreeMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    lines: Vec<(u16, usize)>,
+    line: usize,
+    //macro depth of the last token
+    depth: usize,
+    //0x200 holds `jump main`, patched once main is known
+    entry_jump: bool,
+    main_line: usize,
+}
+
+impl Assembler {
+    fn new(source: &str) -> Assembler {
+        let mut tokens = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let code = line.split('#').next().unwrap_or("");
+            for word in code.split_whitespace() {
+                tokens.push(Token { text: word.to_string(), line: i + 1, depth: 0 });
+            }
+        }
+        let entry_jump = tokens.windows(2).any(|pair| pair[0].text == ":" && pair[1].text == "main");
+        Assembler {
+            tokens,
+            position: 0,
+            rom: if entry_jump { vec![0x10, 0x00] } else { Vec::new() },
+            here: if entry_jump { 2 } else { 0 },
+            labels: BTreeMap::new(),
+            constants: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            macros: BTreeMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            lines: Vec::new(),
+            line: 1,
+            depth: 0,
+            entry_jump,
+            main_line: 0,
+        }
+    }
+
+    fn run(&mut self) -> Result<(), AsmError> {
+        while self.position < self.tokens.len() {
+            self.statement()?;
+        }
+        match self.blocks.last() {
+            Some(&Block::If { .. }) => self.error("`if ... begin` without `end`"),
+            Some(&Block::Loop { .. }) => self.error("`loop` without `again`"),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(mut self) -> Result<Program, AsmError> {
+        for fixup in core::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+            let address = match self.labels.get(&fixup.label) {
+                Some(&address) => address,
+                None => return self.error(format!("undefined label '{}'", fixup.label)),
+            };
+            self.patch(fixup.position, fixup.patch, address)?;
+        }
+        if self.entry_jump {
+            self.line = self.main_line;
+            let main = match self.labels.get("main") {
+                Some(&main) => main,
+                None => return self.error("undefined label 'main'"),
+            };
+            self.patch(0, Patch::Address, main)?;
+            self.lines.insert(0, (ROM_START as u16, self.main_line));
+        }
+        Ok(Program { rom: self.rom, symbols: self.labels, lines: self.lines })
+    }
+
+    fn error<T, M: ToString>(&self, message: M) -> Result<T, AsmError> {
+        Err(AsmError { line: self.line, message: message.to_string() })
+    }
+
+    fn next(&mut self) -> Result<String, AsmError> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.line = token.line;
+                self.depth = token.depth;
+                self.position += 1;
+                Ok(token.text.clone())
+            }
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token != text {
+            return self.error(format!("expected '{}', found '{}'", text, token));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match token.as_str() {
+            ":" => {
+                let name = self.name()?;
+                if name == "main" {
+                    self.main_line = self.line;
+                    //main is the first statement, no jump needed
+                    if self.entry_jump && self.here == 2 && self.rom.len() == 2 && self.labels.is_empty() {
+                        self.entry_jump = false;
+                        self.rom.clear();
+                        self.here = 0;
+                    }
+                }
+                if self.labels.insert(name.clone(), self.address()).is_some() {
+                    return self.error(format!("label '{}' is defined twice", name));
+                }
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let address = self.value()?;
+                if address < ROM_START as i32 || address > 0xFFFF {
+                    return self.error(format!("cannot place code at {:X}", address));
+                }
+                self.here = address as usize - ROM_START;
+            }
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_bytes(&[byte])?;
+            }
+            ":call" => self.address_op(0x2000)?,
+            "clear" => self.emit(0x00E0)?,
+            "return" | ";" => self.emit(0x00EE)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n as u16)?;
+            }
+            "scroll-right" => self.emit(0x00FB)?,
+            "scroll-left" => self.emit(0x00FC)?,
+            "exit" => self.emit(0x00FD)?,
+            "lores" => self.emit(0x00FE)?,
+            "hires" => self.emit(0x00FF)?,
+            "jump" => self.address_op(0x1000)?,
+            "jump0" => self.address_op(0xB000)?,
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | xy(x, y) | n as u16)?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let store = token == "save";
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    self.emit(if store { 0x5002 } else { 0x5003 } | xy(x, y))?;
+                } else {
+                    self.emit(if store { 0xF055 } else { 0xF065 } | xy(x, 0))?;
+                }
+            }
+            "bcd" => self.register_op(0xF033)?,
+            "saveflags" => self.register_op(0xF075)?,
+            "loadflags" => self.register_op(0xF085)?,
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | xy(n, 0))?;
+            }
+            "audio" => self.emit(0xF002)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let opcode = match token.as_str() {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.register_op(opcode)?;
+            }
+            "i" => self.index_statement()?,
+            "if" => self.if_statement()?,
+            "else" => {
+                let end_jump = self.emit_jump()?;
+                match self.blocks.last_mut() {
+                    Some(&mut Block::If { else_jump, end_jump: ref mut end @ None }) => {
+                        *end = Some(end_jump);
+                        let here = self.address();
+                        self.patch(else_jump, Patch::Address, here)?;
+                    }
+                    _ => return self.error("`else` without `if ... begin`"),
+                }
+            }
+            "end" => match self.blocks.pop() {
+                Some(Block::If { else_jump, end_jump }) => {
+                    let here = self.address();
+                    self.patch(end_jump.unwrap_or(else_jump), Patch::Address, here)?;
+                }
+                _ => return self.error("`end` without `if ... begin`"),
+            },
+            "loop" => {
+                let start = self.address();
+                self.blocks.push(Block::Loop { start, breaks: Vec::new() });
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.emit(condition.negate().skip_unless())?;
+                let jump = self.emit_jump()?;
+                match self.blocks.iter_mut().rev().find(|block| matches!(**block, Block::Loop { .. })) {
+                    Some(&mut Block::Loop { ref mut breaks, .. }) => breaks.push(jump),
+                    _ => return self.error("`while` outside of `loop`"),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, breaks }) => {
+                    let jump = self.emit_jump()?;
+                    self.patch(jump, Patch::Address, start)?;
+                    let here = self.address();
+                    for jump in breaks {
+                        self.patch(jump, Patch::Address, here)?;
+                    }
+                }
+                _ => return self.error("`again` without `loop`"),
+            },
+            _ => {
+                if let Some(x) = self.register_of(&token) {
+                    return self.register_statement(x);
+                }
+                if self.macros.contains_key(&token) {
+                    return self.expand_macro(&token);
+                }
+                if self.labels.contains_key(&token) {
+                    self.position -= 1;
+                    return self.address_op(0x2000);
+                }
+                match self.number_of(&token) {
+                    Some(value) => {
+                        let byte = self.check_byte(value)?;
+                        self.emit_bytes(&[byte])?;
+                    }
+                    //a bare label calls it
+                    None if is_identifier(&token) => {
+                        self.position -= 1;
+                        self.address_op(0x2000)?;
+                    }
+                    None => return self.error(format!("unknown statement '{}'", token)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), AsmError> {
+        let op = self.next()?;
+        let operand = self.next()?;
+        let y = self.register_of(&operand);
+        let opcode = match (op.as_str(), y) {
+            (":=", Some(y)) => 0x8000 | xy(x, y),
+            (":=", None) => match operand.as_str() {
+                "random" => {
+                    let nn = self.byte()?;
+                    0xC000 | xy(x, 0) | nn as u16
+                }
+                "delay" => 0xF007 | xy(x, 0),
+                "key" => 0xF00A | xy(x, 0),
+                _ => 0x6000 | xy(x, 0) | self.byte_of(&operand)? as u16,
+            },
+            ("+=", Some(y)) => 0x8004 | xy(x, y),
+            ("+=", None) => 0x7000 | xy(x, 0) | self.byte_of(&operand)? as u16,
+            ("-=", Some(y)) => 0x8005 | xy(x, y),
+            ("-=", None) => 0x7000 | xy(x, 0) | (self.byte_of(&operand)? as u16).wrapping_neg() & 0xFF,
+            ("|=", Some(y)) => 0x8001 | xy(x, y),
+            ("&=", Some(y)) => 0x8002 | xy(x, y),
+            ("^=", Some(y)) => 0x8003 | xy(x, y),
+            (">>=", Some(y)) => 0x8006 | xy(x, y),
+            ("=-", Some(y)) => 0x8007 | xy(x, y),
+            ("<<=", Some(y)) => 0x800E | xy(x, y),
+            _ => return self.error(format!("unsupported operation 'v{:X} {} {}'", x, op, operand)),
+        };
+        self.emit(opcode)
+    }
+
+    fn index_statement(&mut self) -> Result<(), AsmError> {
+        let op = self.next()?;
+        match op.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    self.register_op(0xF029)
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    self.register_op(0xF030)
+                }
+                Some("long") => {
+                    self.next()?;
+                    let token = self.next()?;
+                    let position = self.here;
+                    self.emit(0xF000)?;
+                    self.emit_bytes(&[0, 0])?;
+                    self.reference(&token, position + 2, Patch::Long)
+                }
+                _ => self.address_op(0xA000),
+            },
+            "+=" => self.register_op(0xF01E),
+            _ => self.error(format!("unsupported operation 'i {}'", op)),
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+        let form = self.next()?;
+        match form.as_str() {
+            "then" => self.emit(condition.skip_unless()),
+            "begin" => {
+                self.emit(condition.negate().skip_unless())?;
+                let else_jump = self.emit_jump()?;
+                self.blocks.push(Block::If { else_jump, end_jump: None });
+                Ok(())
+            }
+            _ => self.error(format!("expected 'then' or 'begin', found '{}'", form)),
+        }
+    }
+
+    //`<`, `>`, `<=` and `>=` are compiled like Octo does: the operand is copied to vf,
+    //subtracted and the condition tests the flag, the instructions are emitted right away
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.register()?;
+        let op = self.next()?;
+        match op.as_str() {
+            "key" => return Ok(Condition::Pressed(x)),
+            "-key" => return Ok(Condition::Released(x)),
+            _ => {}
+        }
+        let operand = self.next()?;
+        match (op.as_str(), self.register_of(&operand)) {
+            ("==", Some(y)) => Ok(Condition::Equal(x, y)),
+            ("!=", Some(y)) => Ok(Condition::NotEqual(x, y)),
+            ("==", None) => Ok(Condition::EqualByte(x, self.byte_of(&operand)?)),
+            ("!=", None) => Ok(Condition::NotEqualByte(x, self.byte_of(&operand)?)),
+            ("<", y) | (">", y) | ("<=", y) | (">=", y) => {
+                if x == 0xF {
+                    return self.error(format!("vf cannot be compared with '{}', it holds the operand", op));
+                }
+                match y {
+                    Some(y) => self.emit(0x8F00 | xy(0, y))?,
+                    None => {
+                        let nn = self.byte_of(&operand)?;
+                        self.emit(0x6F00 | nn as u16)?;
+                    }
+                }
+                //vf -= vx sets vf if operand >= vx, vf =- vx if vx >= operand
+                let (subtract, flag) = match op.as_str() {
+                    ">" => (0x5, 0),
+                    "<=" => (0x5, 1),
+                    "<" => (0x7, 0),
+                    _ => (0x7, 1),
+                };
+                self.emit(0x8F00 | xy(0, x) | subtract)?;
+                Ok(Condition::EqualByte(0xF, flag))
+            }
+            _ => self.error(format!("unsupported condition 'v{:X} {} {}'", x, op, operand)),
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            params.push(token);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.tokens.get(self.position).cloned();
+            let token = match token {
+                Some(token) => token,
+                None => return self.error(format!("macro '{}' is missing its '}}'", name)),
+            };
+            self.position += 1;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), AsmError> {
+        let (line, depth) = (self.line, self.depth + 1);
+        if depth > MAX_MACRO_DEPTH {
+            return self.error(format!("macro '{}' expands macros more than {} levels deep, is it recursive?", name, MAX_MACRO_DEPTH));
+        }
+        let count = self.macros[name].params.len();
+        let mut args = Vec::new();
+        for _ in 0..count {
+            args.push(self.next()?);
+        }
+        let expansion: Vec<Token> = self.macros[name].body.iter()
+            .map(|token| {
+                let text = match self.macros[name].params.iter().position(|param| *param == token.text) {
+                    Some(i) => args[i].clone(),
+                    None => token.text.clone(),
+                };
+                Token { text, line, depth }
+            })
+            .collect();
+        let rest = self.tokens.split_off(self.position);
+        self.tokens.extend(expansion);
+        self.tokens.extend(rest);
+        Ok(())
+    }
+
+    fn address(&self) -> u16 {
+        (ROM_START + self.here) as u16
+    }
+
+    fn emit(&mut self, opcode: u16) -> Result<(), AsmError> {
+        self.lines.push((self.address(), self.line));
+        self.emit_bytes(&[(opcode >> 8) as u8, opcode as u8])
+    }
+
+    fn emit_bytes(&mut self, bytes: &[u8]) -> Result<(), AsmError> {
+        let end = self.here + bytes.len();
+        if ROM_START + end > 0x10000 {
+            return self.error("program does not fit into memory");
+        }
+        if self.entry_jump && self.here < 2 {
+            return self.error("0x200 is taken by the jump to main");
+        }
+        if self.rom.len() < end {
+            self.rom.resize(end, 0);
+        }
+        self.rom[self.here..end].copy_from_slice(bytes);
+        self.here = end;
+        Ok(())
+    }
+
+    //emits a jump to be patched later, returns its position
+    fn emit_jump(&mut self) -> Result<usize, AsmError> {
+        let position = self.here;
+        self.emit(0x1000)?;
+        Ok(position)
+    }
+
+    fn patch(&mut self, position: usize, patch: Patch, address: u16) -> Result<(), AsmError> {
+        match patch {
+            Patch::Address => {
+                if address > 0x0FFF {
+                    return self.error(format!("address {:X} is out of range, use 'i := long'", address));
+                }
+                self.rom[position] = (self.rom[position] & 0xF0) | (address >> 8) as u8;
+                self.rom[position + 1] = address as u8;
+            }
+            Patch::Long => {
+                self.rom[position] = (address >> 8) as u8;
+                self.rom[position + 1] = address as u8;
+            }
+        }
+        Ok(())
+    }
+
+    //an instruction taking an address, given as number, constant or label
+    fn address_op(&mut self, opcode: u16) -> Result<(), AsmError> {
+        let token = self.next()?;
+        let position = self.here;
+        self.emit(opcode)?;
+        self.reference(&token, position, Patch::Address)
+    }
+
+    fn reference(&mut self, token: &str, position: usize, patch: Patch) -> Result<(), AsmError> {
+        match self.number_of(token) {
+            Some(value) if (0..=0xFFFF).contains(&value) => self.patch(position, patch, value as u16),
+            Some(value) => self.error(format!("address {} is out of range", value)),
+            None if is_identifier(token) => {
+                self.fixups.push(Fixup { position, patch, label: token.to_string(), line: self.line });
+                Ok(())
+            }
+            None => self.error(format!("expected an address, found '{}'", token)),
+        }
+    }
+
+    fn register_op(&mut self, opcode: u16) -> Result<(), AsmError> {
+        let x = self.register()?;
+        self.emit(opcode | xy(x, 0))
+    }
+
+    fn name(&mut self) -> Result<String, AsmError> {
+        let token = self.next()?;
+        if !is_identifier(&token) || self.register_of(&token).is_some() {
+            return self.error(format!("invalid name '{}'", token));
+        }
+        Ok(token)
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        match self.register_of(&token) {
+            Some(x) => Ok(x),
+            None => self.error(format!("expected a register, found '{}'", token)),
+        }
+    }
+
+    fn register_of(&self, token: &str) -> Option<u8> {
+        if let Some(&x) = self.aliases.get(token) {
+            return Some(x);
+        }
+        let mut chars = token.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) =>
+                digit.to_digit(16).map(|x| x as u8),
+            _ => None,
+        }
+    }
+
+    //a number, a constant or an already defined label
+    fn number_of(&self, token: &str) -> Option<i32> {
+        if let Some(&value) = self.constants.get(token) {
+            return Some(value);
+        }
+        if let Some(&address) = self.labels.get(token) {
+            return Some(address as i32);
+        }
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i32::from_str_radix(hex, 16).ok()?
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i32::from_str_radix(binary, 2).ok()?
+        } else {
+            digits.parse().ok()?
+        };
+        Some(if negative { -value } else { value })
+    }
+
+    fn value(&mut self) -> Result<i32, AsmError> {
+        let token = self.next()?;
+        match self.number_of(&token) {
+            Some(value) => Ok(value),
+            None => self.error(format!("expected a number, found '{}'", token)),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.byte_of(&token)
+    }
+
+    fn byte_of(&self, token: &str) -> Result<u8, AsmError> {
+        match self.number_of(token) {
+            Some(value) => self.check_byte(value),
+            None => self.error(format!("expected a number, found '{}'", token)),
+        }
+    }
+
+    fn check_byte(&self, value: i32) -> Result<u8, AsmError> {
+        if (-128..=255).contains(&value) {
+            Ok(value as u8)
+        } else {
+            self.error(format!("{} does not fit into a byte", value))
+        }
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        let value = self.value()?;
+        if (0..16).contains(&value) {
+            Ok(value as u8)
+        } else {
+            self.error(format!("{} does not fit into a nibble", value))
+        }
+    }
+}
+
+//the X and Y fields of an opcode
+fn xy(x: u8, y: u8) -> u16 {
+    (x as u16) << 8 | (y as u16) << 4
+}
+
+fn is_identifier(token: &str) -> bool {
+    let mut chars = token.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::{Chip8, Mode};
+    use disasm::disassemble;
+    use keypad::Keypad;
+
+    fn rom(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().rom
+    }
+
+    fn error(source: &str) -> AsmError {
+        assemble(source).unwrap_err()
+    }
+
+    #[test]
+    fn statements() {
+        let source = "
+            : main
+            clear
+            v0 := 0x12
+            v1 += 3
+            v0 += v1
+            i := sprite
+            sprite v0 v1 5
+            if v0 == 5 then v1 := 0
+            if v0 != v1 begin v2 := 1 else v2 := 2 end
+            loop
+                v3 += 1
+                while v3 != 8
+            again
+            jump main
+            : sprite 0xF0 0x90
+        ";
+        assert_eq!(rom(source), vec![
+            0x00, 0xE0, 0x60, 0x12, 0x71, 0x03, 0x80, 0x14,
+            0xA2, 0x24, 0xD0, 0x15, 0x40, 0x05, 0x61, 0x00,
+            0x90, 0x10, 0x12, 0x18, 0x62, 0x01, 0x12, 0x1A,
+            0x62, 0x02, 0x73, 0x01, 0x43, 0x08, 0x12, 0x22,
+            0x12, 0x1A, 0x12, 0x00, 0xF0, 0x90,
+        ]);
+    }
+
+    #[test]
+    fn disassembly_assembles_back() {
+        let original = rom("
+            : main
+            v0 := 1
+            loop
+                v0 += 1
+                if v0 == 10 then v0 := 0
+                draw
+            again
+            : draw
+            i := data
+            sprite v0 v0 1
+            ;
+            : data 0x80
+        ");
+        let listing = disassemble(&original, Mode::Chip8);
+        assert_eq!(rom(&listing), original, "{}", listing);
+    }
+
+    #[test]
+    fn main_first_needs_no_jump() {
+        assert_eq!(rom(": main v0 := 1"), vec![0x60, 0x01]);
+        assert_eq!(rom("v0 := 1"), vec![0x60, 0x01]);
+    }
+
+    #[test]
+    fn main_later_gets_a_jump() {
+        let program = assemble(": data 1 2\n: main v0 := 1").unwrap();
+        assert_eq!(program.rom, vec![0x12, 0x04, 0x01, 0x02, 0x60, 0x01]);
+        assert_eq!(program.symbols["main"], 0x204);
+        assert_eq!(program.lines[0], (0x200, 2));
+        assert_eq!(rom(":org 0x300 : main jump main"), {
+            let mut rom = vec![0; 0x102];
+            rom[0] = 0x13;
+            rom[0x100] = 0x13;
+            rom
+        });
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("v0 := 1\njump nowhere"),
+                   AsmError { line: 2, message: "undefined label 'nowhere'".to_string() });
+        assert_eq!(error("if vf < v1 then v0 := 1").message, "vf cannot be compared with '<', it holds the operand");
+        assert_eq!(error("if v0 <> v1 then v0 := 1").message, "unsupported condition 'v0 <> v1'");
+        assert_eq!(error("v0 := 256").line, 1);
+        assert_eq!(error("if v0 == 1 begin v0 := 2").message, "`if ... begin` without `end`");
+        assert_eq!(error("loop v0 += 1").message, "`loop` without `again`");
+        assert_eq!(error(": a : a").message, "label 'a' is defined twice");
+        assert_eq!(error(":org 0x200 v0 := 1 : main").message, "0x200 is taken by the jump to main");
+        assert_eq!(error("jump 0x1000").message, "address 1000 is out of range, use 'i := long'");
+        assert_eq!(error(":org 0x1000 loop v0 += 1 again").message, "address 1000 is out of range, use 'i := long'");
+        assert_eq!(error(":macro twice { twice twice }\nv0 := 1\ntwice"), AsmError {
+            line: 3,
+            message: "macro 'twice' expands macros more than 64 levels deep, is it recursive?".to_string(),
+        });
+    }
+
+    #[test]
+    fn comparisons_go_through_vf() {
+        assert_eq!(rom("if v0 > v1 then v2 := 1"), vec![0x8F, 0x10, 0x8F, 0x05, 0x4F, 0x00, 0x62, 0x01]);
+        assert_eq!(rom("if v0 >= 7 then v2 := 1"), vec![0x6F, 0x07, 0x8F, 0x07, 0x4F, 0x01, 0x62, 0x01]);
+        for &op in ["<", ">", "<=", ">="].iter() {
+            for &(a, b) in [(3, 5), (5, 5), (5, 3), (0, 255)].iter() {
+                let expected = match op {
+                    "<" => a < b,
+                    ">" => a > b,
+                    "<=" => a <= b,
+                    _ => a >= b,
+                };
+                for &operand in ["v1", &b.to_string()].iter() {
+                    let source = format!("v0 := {} v1 := {} if v0 {} {} begin v2 := 1 else v2 := 2 end", a, b, op, operand);
+                    let mut chip8 = Chip8::with_mode(rom(&source), Mode::Chip8).unwrap();
+                    chip8.run_frame(&Keypad::new(), 7).unwrap();
+                    assert_eq!(chip8.registers()[2], if expected { 1 } else { 2 }, "{}", source);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn while_with_a_comparison() {
+        let mut chip8 = Chip8::with_mode(rom("loop v0 += 3 while v0 < 10 again"), Mode::Chip8).unwrap();
+        chip8.run_frame(&Keypad::new(), 40).unwrap();
+        assert_eq!(chip8.registers()[0], 12);
+    }
+}
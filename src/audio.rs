@@ -0,0 +1,198 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+/// Shape of the buzzer tone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Waveform, String> {
+        match s {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" | "saw" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!("unknown waveform '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Waveform::Square => "square",
+            Waveform::Triangle => "triangle",
+            Waveform::Sawtooth => "sawtooth",
+            Waveform::Sine => "sine",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Configuration of the buzzer that sounds while the sound timer is non-zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tone {
+    pub waveform: Waveform,
+    /// Frequency in Hz.
+    pub frequency: f32,
+    /// Volume between 0.0 (silent) and 1.0 (full scale).
+    pub volume: f32,
+}
+
+impl Default for Tone {
+    fn default() -> Tone {
+        Tone {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            volume: 0.25,
+        }
+    }
+}
+
+/// XO-CHIP audio pattern: 128 one-bit samples that are played in a loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pattern {
+    pub bits: [u8; 16],
+    /// Playback pitch as set by FX3A, 64 is 4000 samples per second.
+    pub pitch: u8,
+}
+
+impl Pattern {
+    /// Playback rate in samples per second.
+    pub fn rate(&self) -> f32 {
+        4000.0 * exp2((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.bits[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+}
+
+/// Generates the samples of a `Tone` at a fixed sample rate.
+///
+/// If a `Pattern` is set, it is played with the volume of the tone instead.
+#[derive(Clone, Debug)]
+pub struct Oscillator {
+    tone: Tone,
+    phase: f32,
+    phase_inc: f32,
+    sample_rate: f32,
+    pattern: Option<Pattern>,
+    pattern_phase: f32,
+}
+
+impl Oscillator {
+    pub fn new(tone: Tone, sample_rate: u32) -> Oscillator {
+        Oscillator {
+            tone,
+            phase: 0.0,
+            phase_inc: tone.frequency / sample_rate as f32,
+            sample_rate: sample_rate as f32,
+            pattern: None,
+            pattern_phase: 0.0,
+        }
+    }
+
+    /// Plays `pattern` instead of the tone, or the tone again if `None`.
+    pub fn set_pattern(&mut self, pattern: Option<Pattern>) {
+        self.pattern = pattern;
+    }
+
+    /// Returns the next sample in the range -volume..volume.
+    pub fn next_sample(&mut self) -> f32 {
+        if let Some(pattern) = self.pattern {
+            let bit = pattern.bit(self.pattern_phase as usize % 128);
+            self.pattern_phase += pattern.rate() / self.sample_rate;
+            if self.pattern_phase >= 128.0 {
+                self.pattern_phase -= 128.0;
+            }
+            return if bit { self.tone.volume } else { -self.tone.volume };
+        }
+        let p = self.phase;
+        let value = match self.tone.waveform {
+            Waveform::Square => if p < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => if p < 0.5 { 4.0 * p - 1.0 } else { 3.0 - 4.0 * p },
+            Waveform::Sawtooth => 2.0 * p - 1.0,
+            Waveform::Sine => sine(p),
+        };
+        self.phase += self.phase_inc;
+        if self.phase >= 1.0 || self.phase < 0.0 {
+            //keeps the fraction even if the phase moves by more than a period per sample
+            self.phase -= floor(self.phase);
+        }
+        value * self.tone.volume
+    }
+
+    /// Fills `out` with the next samples.
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+//approximates sin(2 * pi * phase) with two parabolas, close enough for a buzzer
+fn sine(phase: f32) -> f32 {
+    let x = if phase < 0.5 { phase * 2.0 } else { phase * 2.0 - 1.0 };
+    let half = 4.0 * x * (1.0 - x);
+    if phase < 0.5 { half } else { -half }
+}
+
+//rounds towards negative infinity, core has no float functions
+fn floor(x: f32) -> f32 {
+    let whole = x as i64 as f32;
+    if whole > x { whole - 1.0 } else { whole }
+}
+
+//2^x, accurate enough for pitches
+fn exp2(x: f32) -> f32 {
+    let mut whole = x as i32;
+    if (whole as f32) > x {
+        whole -= 1;
+    }
+    let fraction = x - whole as f32;
+    //polynomial fit of 2^f on 0..1
+    let power = 1.0 + fraction * (0.695_976 + fraction * (0.224_494 + fraction * 0.079_440));
+    if whole >= 0 {
+        power * (1u32 << whole.min(31)) as f32
+    } else {
+        power / (1u32 << (-whole).min(31)) as f32
+    }
+}
+
+/// Audio output driven by the emulator once per frame.
+pub trait Audio {
+    /// Starts or stops the buzzer.
+    fn set_playing(&mut self, playing: bool);
+
+    /// Selects the XO-CHIP pattern to play instead of the buzzer tone.
+    fn set_pattern(&mut self, _pattern: Option<Pattern>) {}
+}
+
+/// Audio output that discards everything.
+pub struct NullAudio;
+
+impl Audio for NullAudio {
+    fn set_playing(&mut self, _playing: bool) {}
+}
+
+/// Audio output that remembers the buzzer state of every frame instead of playing it.
+#[derive(Clone, Debug, Default)]
+pub struct RecordingAudio {
+    pub frames: Vec<bool>,
+}
+
+impl Audio for RecordingAudio {
+    fn set_playing(&mut self, playing: bool) {
+        self.frames.push(playing);
+    }
+}
@@ -0,0 +1,624 @@
+use chip8::Chip8;
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+use core::str::FromStr;
+
+/// A value of the machine that watchpoints and conditions can look at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    /// V[0] to V[F].
+    V(u8),
+    /// The index register I.
+    I,
+    Pc,
+    /// Number of return addresses on the stack.
+    Sp,
+    Delay,
+    Sound,
+}
+
+impl Register {
+    /// Reads the current value from `chip8`.
+    pub fn value(&self, chip8: &Chip8) -> u16 {
+        match *self {
+            Register::V(x) => chip8.registers()[x as usize & 0xF] as u16,
+            Register::I => chip8.index(),
+            Register::Pc => chip8.pc(),
+            Register::Sp => chip8.stack().len() as u16,
+            Register::Delay => chip8.delay_timer() as u16,
+            Register::Sound => chip8.sound_timer() as u16,
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    /// Parses `V0` to `VF`, `I`, `PC`, `SP`, `DT` and `ST`, in any case.
+    fn from_str(s: &str) -> Result<Register, String> {
+        let name = s.to_ascii_uppercase();
+        match name.as_str() {
+            "I" => Ok(Register::I),
+            "PC" => Ok(Register::Pc),
+            "SP" => Ok(Register::Sp),
+            "DT" => Ok(Register::Delay),
+            "ST" => Ok(Register::Sound),
+            _ if name.len() == 2 && name.starts_with('V') => match u8::from_str_radix(&name[1..], 16) {
+                Ok(x) => Ok(Register::V(x)),
+                Err(_) => Err(format!("unknown register '{}'", s)),
+            },
+            _ => Err(format!("unknown register '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+            Register::Delay => write!(f, "DT"),
+            Register::Sound => write!(f, "ST"),
+        }
+    }
+}
+
+/// One side of a comparison.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Value(u16),
+}
+
+impl Operand {
+    fn value(&self, chip8: &Chip8) -> u16 {
+        match *self {
+            Operand::Register(register) => register.value(chip8),
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Register(register) => write!(f, "{}", register),
+            Operand::Value(value) => write!(f, "0x{:X}", value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    fn symbol(&self) -> &'static str {
+        match *self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterEqual => ">=",
+        }
+    }
+}
+
+/// A condition over the registers, e.g. `V3 == 5 && I > 0x300`.
+///
+/// Comparisons can be combined with `&&` and `||`, `&&` binds tighter and
+/// parentheses group. Numbers are decimal or hex with a `0x` prefix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Compare(Operand, Comparison, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    /// Evaluates the condition against the current state of `chip8`.
+    pub fn eval(&self, chip8: &Chip8) -> bool {
+        match *self {
+            Condition::Compare(left, comparison, right) => {
+                let (left, right) = (left.value(chip8), right.value(chip8));
+                match comparison {
+                    Comparison::Equal => left == right,
+                    Comparison::NotEqual => left != right,
+                    Comparison::Less => left < right,
+                    Comparison::LessEqual => left <= right,
+                    Comparison::Greater => left > right,
+                    Comparison::GreaterEqual => left >= right,
+                }
+            }
+            Condition::And(ref left, ref right) => left.eval(chip8) && right.eval(chip8),
+            Condition::Or(ref left, ref right) => left.eval(chip8) || right.eval(chip8),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Condition, String> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens: &tokens, position: 0 };
+        let condition = parser.or()?;
+        match parser.peek() {
+            None => Ok(condition),
+            Some(token) => Err(format!("unexpected '{}'", token)),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Condition::Compare(left, comparison, right) => write!(f, "{} {} {}", left, comparison.symbol(), right),
+            Condition::And(ref left, ref right) => {
+                //only an || inside an && needs parentheses
+                for (i, side) in [left, right].iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " && ")?;
+                    }
+                    match ***side {
+                        Condition::Or(..) => write!(f, "({})", side)?,
+                        _ => write!(f, "{}", side)?,
+                    }
+                }
+                Ok(())
+            }
+            Condition::Or(ref left, ref right) => write!(f, "{} || {}", left, right),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        } else if c == '(' || c == ')' {
+            tokens.push(c.to_string());
+            chars.next();
+        } else {
+            let mut symbol = String::new();
+            while let Some(&c) = chars.peek() {
+                if !"=!<>&|".contains(c) {
+                    break;
+                }
+                symbol.push(c);
+                chars.next();
+            }
+            if symbol.is_empty() {
+                return Err(format!("unexpected '{}'", c));
+            }
+            tokens.push(symbol);
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens: or := and ('||' and)*, and := term ('&&' term)*.
+struct Parser<'a> {
+    tokens: &'a [String],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|token| token.as_str())
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let token = self.peek().ok_or_else(|| String::from("unexpected end of condition"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and()?;
+        while self.peek() == Some("||") {
+            self.position += 1;
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, String> {
+        let mut condition = self.term()?;
+        while self.peek() == Some("&&") {
+            self.position += 1;
+            condition = Condition::And(Box::new(condition), Box::new(self.term()?));
+        }
+        Ok(condition)
+    }
+
+    fn term(&mut self) -> Result<Condition, String> {
+        if self.peek() == Some("(") {
+            self.position += 1;
+            let condition = self.or()?;
+            return match self.next()? {
+                ")" => Ok(condition),
+                token => Err(format!("expected ')' instead of '{}'", token)),
+            };
+        }
+        let left = self.operand()?;
+        let comparison = match self.next()? {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessEqual,
+            ">" => Comparison::Greater,
+            ">=" => Comparison::GreaterEqual,
+            token => return Err(format!("expected a comparison instead of '{}'", token)),
+        };
+        let right = self.operand()?;
+        Ok(Condition::Compare(left, comparison, right))
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let token = self.next()?;
+        if token.starts_with(|c: char| c.is_ascii_digit()) {
+            let value = if token.starts_with("0x") || token.starts_with("0X") {
+                u16::from_str_radix(&token[2..], 16)
+            } else {
+                token.parse()
+            };
+            return value.map(Operand::Value).map_err(|_| format!("invalid number '{}'", token));
+        }
+        token.parse().map(Operand::Register)
+    }
+}
+
+/// What a breakpoint watches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Watch {
+    /// Stops once pc reaches the address, before the instruction there runs.
+    Execute(u16),
+    /// Stops after an instruction read a byte in the range.
+    Read(Range<usize>),
+    /// Stops after an instruction wrote a byte in the range.
+    Write(Range<usize>),
+    /// Stops after an instruction changed the register.
+    Register(Register),
+    /// Stops when the condition turns from false to true.
+    Condition(Condition),
+}
+
+/// A breakpoint or watchpoint, optionally only stopping while `condition` holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub watch: Watch,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    pub fn new(watch: Watch) -> Breakpoint {
+        Breakpoint { watch, condition: None }
+    }
+
+    /// Adds a condition that must hold for the breakpoint to stop.
+    pub fn when(mut self, condition: Condition) -> Breakpoint {
+        self.condition = Some(condition);
+        self
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.watch {
+            Watch::Execute(address) => write!(f, "break at {:03X}", address)?,
+            Watch::Read(ref range) => write!(f, "watch reads of {:03X}..{:03X}", range.start, range.end)?,
+            Watch::Write(ref range) => write!(f, "watch writes to {:03X}..{:03X}", range.start, range.end)?,
+            Watch::Register(register) => write!(f, "watch {}", register)?,
+            Watch::Condition(ref condition) => write!(f, "stop when {}", condition)?,
+        }
+        match self.condition {
+            Some(ref condition) => write!(f, " if {}", condition),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What made a breakpoint stop the machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Execute(u16),
+    Read(usize),
+    Write(usize),
+    Register { register: Register, old: u16, new: u16 },
+    Condition,
+}
+
+/// The breakpoint that stopped the machine, `id` as returned by `Breakpoints::add`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hit {
+    pub id: usize,
+    pub event: Event,
+}
+
+impl fmt::Display for Hit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.event {
+            Event::Execute(address) => write!(f, "breakpoint {} at {:03X}", self.id, address),
+            Event::Read(address) => write!(f, "watchpoint {}: read of {:03X}", self.id, address),
+            Event::Write(address) => write!(f, "watchpoint {}: write to {:03X}", self.id, address),
+            Event::Register { register, old, new } =>
+                write!(f, "watchpoint {}: {} changed from {:X} to {:X}", self.id, register, old, new),
+            Event::Condition => write!(f, "condition {} became true", self.id),
+        }
+    }
+}
+
+/// Why `Chip8::run_until` returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Hit(Hit),
+    /// The program exited with 00FD.
+    Exited,
+    /// All cycles ran without stopping.
+    Limit,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    id: usize,
+    breakpoint: Breakpoint,
+    //register value or condition result before the current instruction
+    before: u16,
+}
+
+/// The breakpoints of a machine, checked by `Chip8::run_cycle`.
+#[derive(Clone, Debug, Default)]
+pub struct Breakpoints {
+    entries: Vec<Entry>,
+    next_id: usize,
+}
+
+impl Breakpoints {
+    pub fn new() -> Breakpoints {
+        Breakpoints::default()
+    }
+
+    /// Adds a breakpoint and returns its id, ids start at 1 and are never reused.
+    pub fn add(&mut self, breakpoint: Breakpoint) -> usize {
+        self.next_id += 1;
+        self.entries.push(Entry { id: self.next_id, breakpoint, before: 0 });
+        self.next_id
+    }
+
+    /// Removes a breakpoint, returns false if there is none with `id`.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.entries.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Breakpoint> {
+        self.entries.iter().find(|entry| entry.id == id).map(|entry| &entry.breakpoint)
+    }
+
+    /// Iterates over the ids and breakpoints in the order they were added.
+    pub fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (usize, &'a Breakpoint)> + 'a> {
+        Box::new(self.entries.iter().map(|entry| (entry.id, &entry.breakpoint)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remembers the watched registers and conditions before an instruction runs.
+    pub(crate) fn before_cycle(&mut self, chip8: &Chip8) {
+        for entry in self.entries.iter_mut() {
+            entry.before = match entry.breakpoint.watch {
+                Watch::Register(register) => register.value(chip8),
+                Watch::Condition(ref condition) => condition.eval(chip8) as u16,
+                _ => continue,
+            };
+        }
+    }
+
+    /// Returns the first breakpoint hit by the instruction that just ran.
+    ///
+    /// `accesses` are the memory accesses of the instruction, true for writes.
+    pub(crate) fn after_cycle(&self, chip8: &Chip8, accesses: &[(usize, bool)]) -> Option<Hit> {
+        for entry in self.entries.iter() {
+            let event = match entry.breakpoint.watch {
+                Watch::Execute(address) if chip8.pc() == address => Event::Execute(address),
+                Watch::Read(ref range) => match accesses.iter().find(|&&(address, write)| !write && range.contains(&address)) {
+                    Some(&(address, _)) => Event::Read(address),
+                    None => continue,
+                },
+                Watch::Write(ref range) => match accesses.iter().find(|&&(address, write)| write && range.contains(&address)) {
+                    Some(&(address, _)) => Event::Write(address),
+                    None => continue,
+                },
+                Watch::Register(register) => {
+                    let new = register.value(chip8);
+                    if new == entry.before {
+                        continue;
+                    }
+                    Event::Register { register, old: entry.before, new }
+                }
+                Watch::Condition(ref condition) if entry.before == 0 && condition.eval(chip8) => Event::Condition,
+                _ => continue,
+            };
+            if entry.breakpoint.condition.as_ref().is_none_or(|condition| condition.eval(chip8)) {
+                return Some(Hit { id: entry.id, event });
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::Mode;
+    use keypad::Keypad;
+
+    //V0 = 5, stores it at 300, reads it back and loops at 20A
+    fn machine() -> Chip8 {
+        let rom = [0x60, 0x05, 0xA3, 0x00, 0xF0, 0x55, 0xA3, 0x00, 0xF0, 0x65, 0x12, 0x0A];
+        Chip8::with_mode(rom.to_vec(), Mode::Chip8).unwrap()
+    }
+
+    //the stop and the number of instructions it took
+    fn run(chip8: &mut Chip8, breakpoint: Breakpoint) -> (Stop, u64) {
+        chip8.breakpoints.add(breakpoint);
+        let stop = chip8.run_until(&Keypad::new(), 10).unwrap();
+        (stop, chip8.cycles())
+    }
+
+    fn event(watch: Watch) -> Option<(Event, u64)> {
+        let mut chip8 = machine();
+        match run(&mut chip8, Breakpoint::new(watch)) {
+            (Stop::Hit(hit), cycles) => {
+                assert_eq!(chip8.last_hit(), Some(&hit));
+                Some((hit.event, cycles))
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn execute_stops_before_the_instruction() {
+        assert_eq!(event(Watch::Execute(0x204)), Some((Event::Execute(0x204), 2)));
+        assert_eq!(event(Watch::Execute(0x20C)), None);
+    }
+
+    #[test]
+    fn memory_watchpoints() {
+        assert_eq!(event(Watch::Write(0x2FF..0x301)), Some((Event::Write(0x300), 3)));
+        assert_eq!(event(Watch::Read(0x300..0x301)), Some((Event::Read(0x300), 5)));
+        assert_eq!(event(Watch::Read(0x301..0x310)), None);
+    }
+
+    #[test]
+    fn register_watchpoints() {
+        let changed = Event::Register { register: Register::V(0), old: 0, new: 5 };
+        assert_eq!(event(Watch::Register(Register::V(0))), Some((changed, 1)));
+        let changed = Event::Register { register: Register::I, old: 0, new: 0x300 };
+        assert_eq!(event(Watch::Register(Register::I)), Some((changed, 2)));
+        //F065 reads back the same value
+        let mut chip8 = machine();
+        chip8.breakpoints.add(Breakpoint::new(Watch::Register(Register::V(0))));
+        chip8.run_until(&Keypad::new(), 1).unwrap();
+        assert_eq!(chip8.run_until(&Keypad::new(), 10).unwrap(), Stop::Limit);
+    }
+
+    #[test]
+    fn conditions_stop_when_they_turn_true() {
+        let condition = "V0 == 5".parse().unwrap();
+        let mut chip8 = machine();
+        assert_eq!(run(&mut chip8, Breakpoint::new(Watch::Condition(condition))).1, 1);
+        assert_eq!(chip8.last_hit().map(|hit| hit.event), Some(Event::Condition));
+        //it stays true, so it does not stop again
+        assert_eq!(chip8.run_until(&Keypad::new(), 10).unwrap(), Stop::Limit);
+        //true from the start is not a change either
+        assert_eq!(event(Watch::Condition("V1 == 0".parse().unwrap())), None);
+    }
+
+    #[test]
+    fn conditional_breakpoints() {
+        let watch = Watch::Register(Register::I);
+        let mut chip8 = machine();
+        let breakpoint = Breakpoint::new(watch.clone()).when("V0 != 5".parse().unwrap());
+        assert_eq!(run(&mut chip8, breakpoint).0, Stop::Limit);
+        let mut chip8 = machine();
+        let breakpoint = Breakpoint::new(watch).when("V0 == 5".parse().unwrap());
+        assert!(match run(&mut chip8, breakpoint) {
+            (Stop::Hit(hit), 2) => hit.id == 1,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn remove() {
+        let mut breakpoints = Breakpoints::new();
+        let first = breakpoints.add(Breakpoint::new(Watch::Execute(0x204)));
+        let second = breakpoints.add(Breakpoint::new(Watch::Register(Register::I)));
+        assert!(breakpoints.remove(first));
+        assert!(!breakpoints.remove(first));
+        assert_eq!(breakpoints.add(Breakpoint::new(Watch::Execute(0x206))), 3);
+        assert_eq!(breakpoints.iter().map(|(id, _)| id).collect::<Vec<_>>(), [second, 3]);
+        assert_eq!(breakpoints.get(first), None);
+
+        let mut chip8 = machine();
+        let id = chip8.breakpoints.add(Breakpoint::new(Watch::Execute(0x204)));
+        chip8.breakpoints.remove(id);
+        assert_eq!(chip8.run_until(&Keypad::new(), 10).unwrap(), Stop::Limit);
+        assert_eq!(chip8.last_hit(), None);
+    }
+
+    fn compare(left: Operand, comparison: Comparison, right: u16) -> Condition {
+        Condition::Compare(left, comparison, Operand::Value(right))
+    }
+
+    #[test]
+    fn parse_conditions() {
+        let v3 = || compare(Operand::Register(Register::V(3)), Comparison::Equal, 5);
+        let i = || compare(Operand::Register(Register::I), Comparison::Greater, 0x300);
+        let pc = || compare(Operand::Register(Register::Pc), Comparison::LessEqual, 0x200);
+        let and = |left, right| Condition::And(Box::new(left), Box::new(right));
+        let or = |left, right| Condition::Or(Box::new(left), Box::new(right));
+
+        //&& binds tighter than ||
+        let condition = "v3 == 5 || I > 0x300 && pc <= 512".parse::<Condition>().unwrap();
+        assert_eq!(condition, or(v3(), and(i(), pc())));
+        let condition = "V3==5&&I>0x300||PC<=0x200".parse::<Condition>().unwrap();
+        assert_eq!(condition, or(and(v3(), i()), pc()));
+        let condition = "(V3 == 5 || I > 0x300) && PC <= 0x200".parse::<Condition>().unwrap();
+        assert_eq!(condition, and(or(v3(), i()), pc()));
+        assert_eq!(condition.to_string(), "(V3 == 0x5 || I > 0x300) && PC <= 0x200");
+        assert_eq!(condition.to_string().parse::<Condition>(), Ok(condition));
+
+        let condition = "VF != V0".parse::<Condition>().unwrap();
+        let registers = Condition::Compare(Operand::Register(Register::V(15)), Comparison::NotEqual, Operand::Register(Register::V(0)));
+        assert_eq!(condition, registers);
+
+        for &(text, error) in [
+            ("V3 ==", "unexpected end of condition"),
+            ("V3 = 5", "expected a comparison instead of '='"),
+            ("(V3 == 5", "unexpected end of condition"),
+            ("V3 == 5)", "unexpected ')'"),
+            ("VG == 1", "unknown register 'VG'"),
+            ("V3 == 0x10000", "invalid number '0x10000'"),
+            ("V3 == 5 ; I", "unexpected ';'"),
+        ].iter() {
+            assert_eq!(text.parse::<Condition>(), Err(String::from(error)), "{}", text);
+        }
+    }
+}
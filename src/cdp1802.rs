@@ -0,0 +1,398 @@
+use core::fmt;
+
+/// Errors of machine-code routines run on the `Cdp1802`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cdp1802Error {
+    /// The opcode is not part of the CDP1802 instruction set.
+    InvalidOpcode { opcode: u8, address: u16 },
+    /// IDL was executed, nothing would ever wake the CPU up again.
+    Idle { address: u16 },
+    /// The routine did not return within the allowed number of instructions.
+    Timeout { steps: usize },
+}
+
+impl fmt::Display for Cdp1802Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Cdp1802Error::InvalidOpcode { opcode, address } =>
+                write!(f, "invalid CDP1802 opcode {:02X} at {:04X}", opcode, address),
+            Cdp1802Error::Idle { address } =>
+                write!(f, "CDP1802 went idle at {:04X}", address),
+            Cdp1802Error::Timeout { steps } =>
+                write!(f, "CDP1802 routine did not return within {} instructions", steps),
+        }
+    }
+}
+
+/// Memory seen by the `Cdp1802`.
+pub trait Bus {
+    /// Reads the byte at `address`, both for instructions and data.
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, byte: u8);
+}
+
+/// RCA CDP1802 CPU as used in the COSMAC VIP.
+///
+/// Memory is passed to every step, so the CPU can work on the memory of the
+/// CHIP-8 interpreter. Addresses of a `[u8]` wrap around its size, like the
+/// partially decoded address bus of the VIP.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cdp1802 {
+    /// The scratchpad registers R0 to RF.
+    pub r: [u16; 16],
+    /// Accumulator.
+    pub d: u8,
+    /// Data flag (carry/borrow).
+    pub df: bool,
+    /// Selects the program counter register.
+    pub p: u8,
+    /// Selects the data pointer register.
+    pub x: u8,
+    /// Holds X and P after an interrupt or MARK.
+    pub t: u8,
+    /// Interrupt enable.
+    pub ie: bool,
+    /// Q output flip-flop.
+    pub q: bool,
+    /// External flag inputs EF1 to EF4, tested by the branch instructions.
+    pub ef: [bool; 4],
+    /// Value on the data bus read by the INP instructions.
+    pub bus: u8,
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Cdp1802 {
+        Cdp1802::new()
+    }
+}
+
+impl Cdp1802 {
+    /// Creates a CPU in the state after a reset.
+    pub fn new() -> Cdp1802 {
+        Cdp1802 {
+            r: [0; 16],
+            d: 0,
+            df: false,
+            p: 0,
+            x: 0,
+            t: 0,
+            ie: true,
+            q: false,
+            ef: [false; 4],
+            bus: 0,
+        }
+    }
+
+    /// Runs until the CPU executes SEP `p`, or fails after `max_steps` instructions.
+    /// Returns the number of executed instructions.
+    pub fn run_until_sep<M: Bus + ?Sized>(&mut self, memory: &mut M, p: u8, max_steps: usize) -> Result<usize, Cdp1802Error> {
+        for step in 1..=max_steps {
+            self.step(memory)?;
+            if self.p == p {
+                return Ok(step);
+            }
+        }
+        Err(Cdp1802Error::Timeout { steps: max_steps })
+    }
+
+    /// Executes a single instruction.
+    pub fn step<M: Bus + ?Sized>(&mut self, memory: &mut M) -> Result<(), Cdp1802Error> {
+        let address = self.r[self.p as usize];
+        let opcode = self.fetch(memory);
+        let n = (opcode & 0x0F) as usize;
+        let x = self.x as usize;
+
+        match opcode >> 4 {
+            0x0 if n == 0 => return Err(Cdp1802Error::Idle { address }),
+            //LDN: D = M(R(N))
+            0x0 => self.d = memory.read(self.r[n]),
+            //INC: R(N) += 1
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),
+            //DEC: R(N) -= 1
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),
+            0x3 => self.short_branch(memory, n),
+            //LDA: D = M(R(N)), R(N) += 1
+            0x4 => {
+                self.d = memory.read(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            //STR: M(R(N)) = D
+            0x5 => memory.write(self.r[n], self.d),
+            //IRX: R(X) += 1
+            0x6 if n == 0 => self.r[x] = self.r[x].wrapping_add(1),
+            //OUT N: put M(R(X)) on the bus, R(X) += 1, no output devices are attached
+            0x6 if n < 8 => self.r[x] = self.r[x].wrapping_add(1),
+            0x6 if n == 8 => return Err(Cdp1802Error::InvalidOpcode { opcode, address }),
+            //INP N: M(R(X)) = D = bus
+            0x6 => {
+                self.d = self.bus;
+                memory.write(self.r[x], self.bus);
+            }
+            0x7 => self.op_7n(memory, n),
+            //GLO: D = R(N).0
+            0x8 => self.d = self.r[n] as u8,
+            //GHI: D = R(N).1
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            //PLO: R(N).0 = D
+            0xA => self.r[n] = (self.r[n] & 0xFF00) | self.d as u16,
+            //PHI: R(N).1 = D
+            0xB => self.r[n] = (self.r[n] & 0x00FF) | ((self.d as u16) << 8),
+            0xC => self.long_branch(memory, n),
+            //SEP: P = N
+            0xD => self.p = n as u8,
+            //SEX: X = N
+            0xE => self.x = n as u8,
+            _ => self.op_fn(memory, n),
+        }
+        Ok(())
+    }
+
+    fn fetch<M: Bus + ?Sized>(&mut self, memory: &M) -> u8 {
+        let pc = &mut self.r[self.p as usize];
+        let byte = memory.read(*pc);
+        *pc = pc.wrapping_add(1);
+        byte
+    }
+
+    fn short_branch<M: Bus + ?Sized>(&mut self, memory: &M, n: usize) {
+        //3N: branch within the current page to the address in the next byte
+        let condition = match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            ef => self.ef[ef - 4],
+        };
+        //38 (SKP) is the inverse of 30 (BR), i.e. it always skips the address byte
+        let condition = if n & 0x8 != 0 { !condition } else { condition };
+        let target = self.fetch(memory);
+        if condition {
+            let pc = &mut self.r[self.p as usize];
+            *pc = (pc.wrapping_sub(1) & 0xFF00) | target as u16;
+        }
+    }
+
+    fn long_branch<M: Bus + ?Sized>(&mut self, memory: &M, n: usize) {
+        //CN: long branches to the address in the next two bytes, or long skips over them
+        let (skip, condition) = match n {
+            0x0 => (false, true),
+            0x1 => (false, self.q),
+            0x2 => (false, self.d == 0),
+            0x3 => (false, self.df),
+            //NOP
+            0x4 => (true, false),
+            0x5 => (true, !self.q),
+            0x6 => (true, self.d != 0),
+            0x7 => (true, !self.df),
+            0x8 => (true, true),
+            0x9 => (false, !self.q),
+            0xA => (false, self.d != 0),
+            0xB => (false, !self.df),
+            0xC => (true, self.ie),
+            0xD => (true, self.q),
+            0xE => (true, self.d == 0),
+            _ => (true, self.df),
+        };
+        let pc = self.r[self.p as usize];
+        self.r[self.p as usize] = match (skip, condition) {
+            (false, true) => ((memory.read(pc) as u16) << 8) | memory.read(pc.wrapping_add(1)) as u16,
+            (true, false) => pc,
+            _ => pc.wrapping_add(2),
+        };
+    }
+
+    fn op_7n<M: Bus + ?Sized>(&mut self, memory: &mut M, n: usize) {
+        let x = self.x as usize;
+        match n {
+            //RET, DIS: X,P = M(R(X)), R(X) += 1, enable/disable interrupts
+            0x0 | 0x1 => {
+                let value = memory.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = value >> 4;
+                self.p = value & 0x0F;
+                self.ie = n == 0;
+            }
+            //LDXA: D = M(R(X)), R(X) += 1
+            0x2 => {
+                self.d = memory.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            //STXD: M(R(X)) = D, R(X) -= 1
+            0x3 => {
+                memory.write(self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            //ADC: D = M(R(X)) + D + DF
+            0x4 => {
+                let m = memory.read(self.r[x]);
+                self.add(m, self.df);
+            }
+            //SDB: D = M(R(X)) - D - !DF
+            0x5 => {
+                let m = memory.read(self.r[x]);
+                self.subtract(m, self.d, self.df);
+            }
+            //SHRC: shift D right, DF into the MSB, LSB into DF
+            0x6 => {
+                let carry = self.d & 0x01 != 0;
+                self.d = (self.d >> 1) | ((self.df as u8) << 7);
+                self.df = carry;
+            }
+            //SMB: D = D - M(R(X)) - !DF
+            0x7 => {
+                let m = memory.read(self.r[x]);
+                self.subtract(self.d, m, self.df);
+            }
+            //SAV: M(R(X)) = T
+            0x8 => memory.write(self.r[x], self.t),
+            //MARK: T = X,P, M(R(2)) = T, X = P, R(2) -= 1
+            0x9 => {
+                self.t = (self.x << 4) | self.p;
+                memory.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            //REQ, SEQ: reset/set Q
+            0xA => self.q = false,
+            0xB => self.q = true,
+            //ADCI: D = M(R(P)) + D + DF, R(P) += 1
+            0xC => {
+                let m = self.fetch(memory);
+                self.add(m, self.df);
+            }
+            //SDBI: D = M(R(P)) - D - !DF, R(P) += 1
+            0xD => {
+                let m = self.fetch(memory);
+                self.subtract(m, self.d, self.df);
+            }
+            //SHLC: shift D left, DF into the LSB, MSB into DF
+            0xE => {
+                let carry = self.d & 0x80 != 0;
+                self.d = (self.d << 1) | self.df as u8;
+                self.df = carry;
+            }
+            //SMBI: D = D - M(R(P)) - !DF, R(P) += 1
+            _ => {
+                let m = self.fetch(memory);
+                self.subtract(self.d, m, self.df);
+            }
+        }
+    }
+
+    fn op_fn<M: Bus + ?Sized>(&mut self, memory: &mut M, n: usize) {
+        //F0-F7 operate on M(R(X)), F8-FF on the immediate byte M(R(P))
+        if n == 0x6 {
+            //SHR: shift D right, LSB into DF
+            self.df = self.d & 0x01 != 0;
+            self.d >>= 1;
+            return;
+        }
+        if n == 0xE {
+            //SHL: shift D left, MSB into DF
+            self.df = self.d & 0x80 != 0;
+            self.d <<= 1;
+            return;
+        }
+        let m = if n < 8 {
+            memory.read(self.r[self.x as usize])
+        } else {
+            self.fetch(memory)
+        };
+        match n & 0x7 {
+            //LDX, LDI
+            0x0 => self.d = m,
+            //OR, ORI
+            0x1 => self.d |= m,
+            //AND, ANI
+            0x2 => self.d &= m,
+            //XOR, XRI
+            0x3 => self.d ^= m,
+            //ADD, ADI
+            0x4 => self.add(m, false),
+            //SD, SDI: D = M - D
+            0x5 => self.subtract(m, self.d, true),
+            //SM, SMI: D = D - M
+            _ => self.subtract(self.d, m, true),
+        }
+    }
+
+    fn add(&mut self, m: u8, carry: bool) {
+        let sum = self.d as u16 + m as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    //DF is the inverted borrow, a missing DF (`no_borrow` = false) subtracts one more
+    fn subtract(&mut self, a: u8, b: u8, no_borrow: bool) {
+        let difference = a as i16 - b as i16 - (!no_borrow) as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+impl Bus for [u8] {
+    fn read(&self, address: u16) -> u8 {
+        self[address as usize % self.len()]
+    }
+
+    fn write(&mut self, address: u16, byte: u8) {
+        let len = self.len();
+        self[address as usize % len] = byte;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //runs `code` at 0 until SEP R4
+    fn run(code: &[u8]) -> (Cdp1802, Vec<u8>) {
+        let mut memory = vec![0; 0x100];
+        memory[..code.len()].copy_from_slice(code);
+        let mut cpu = Cdp1802::new();
+        cpu.run_until_sep(&mut memory[..], 4, 100).unwrap();
+        (cpu, memory)
+    }
+
+    #[test]
+    fn arithmetic() {
+        //LDI F0, ADI 20: carry
+        let (cpu, _) = run(&[0xF8, 0xF0, 0xFC, 0x20, 0xD4]);
+        assert_eq!((cpu.d, cpu.df), (0x10, true));
+        //LDI 05, SMI 07: borrow, DF is cleared
+        let (cpu, _) = run(&[0xF8, 0x05, 0xFF, 0x07, 0xD4]);
+        assert_eq!((cpu.d, cpu.df), (0xFE, false));
+        //LDI 81, SHR
+        let (cpu, _) = run(&[0xF8, 0x81, 0xF6, 0xD4]);
+        assert_eq!((cpu.d, cpu.df), (0x40, true));
+    }
+
+    #[test]
+    fn load_and_store_through_registers() {
+        //R6 = 0x80, LDI 42, STR R6, INC R6, LDI 43, STR R6, LDN R6
+        let (cpu, memory) = run(&[0xF8, 0x80, 0xA6, 0xF8, 0x42, 0x56, 0x16, 0xF8, 0x43, 0x56, 0x06, 0xD4]);
+        assert_eq!(&memory[0x80..0x82], &[0x42, 0x43]);
+        assert_eq!((cpu.d, cpu.r[6]), (0x43, 0x81));
+    }
+
+    #[test]
+    fn branches() {
+        //LDI 00, BZ 06, LDI 01, SEP 4 | 06: LDI 02, SEP 4
+        let (cpu, _) = run(&[0xF8, 0x00, 0x32, 0x06, 0xF8, 0x01, 0xF8, 0x02, 0xD4]);
+        assert_eq!(cpu.d, 0x02);
+        //LBR 0010
+        let mut code = vec![0xC0, 0x00, 0x10];
+        code.resize(0x10, 0);
+        code.extend_from_slice(&[0xF8, 0x07, 0xD4]);
+        assert_eq!(run(&code).0.d, 0x07);
+    }
+
+    #[test]
+    fn errors() {
+        let mut memory = [0x00; 0x10];
+        assert_eq!(Cdp1802::new().step(&mut memory[..]), Err(Cdp1802Error::Idle { address: 0 }));
+        //BR 00 loops forever
+        let mut memory = [0x30, 0x00];
+        assert_eq!(Cdp1802::new().run_until_sep(&mut memory[..], 4, 10), Err(Cdp1802Error::Timeout { steps: 10 }));
+    }
+}
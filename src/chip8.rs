@@ -1,21 +1,144 @@
-extern crate rand;
-extern crate sdl2;
+use audio::Pattern;
+use breakpoint::{Breakpoints, Hit, Stop};
+use cdp1802::{Bus, Cdp1802};
+use error::{Chip8Error, ErrorMode};
+use input::Input;
+use keypad::Keypad;
+use movie::rom_hash;
+use quirks::{LoadStore, Quirks};
+use rng::{Rng, Xorshift, DEFAULT_SEED};
+use state::{StateError, StateReader, StateWriter};
+use trace::{Level, Message, Record, Step, Target, Tracing};
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::str::FromStr;
 
+/// Width of the display in hi-res mode, the low resolution is half of it.
+pub const DISPLAY_WIDTH: usize = 128;
+/// Height of the display in hi-res mode, the low resolution is half of it.
+pub const DISPLAY_HEIGHT: usize = 64;
+
+/// Number of drawing planes of XO-CHIP, each pixel holds one bit per plane.
+pub const PLANES: usize = 2;
+
+//COSMAC VIP memory layout mirrored for 0NNN machine code routines
+const VIP_STACK: u16 = 0x0ECF;
+const VIP_REGISTERS: usize = 0x0EF0;
+const VIP_DISPLAY: usize = 0x0F00;
+//a routine taking longer than this is assumed to be stuck
+const MACHINE_CODE_MAX_STEPS: usize = 1_000_000;
+
+/// Start of the big 8x10 font used by FX30, right after the small font.
+const BIG_FONT_ADDRESS: usize = 0x50;
+
+/// A CHIP-8 machine. Cloning it is a cheap in-memory snapshot, see `Rewind`.
+#[derive(Clone)]
 pub struct Chip8 {
-    memory: [u8; 4096],
+    //4 KiB, or 64 KiB in XO-CHIP mode
+    memory: Vec<u8>,
     register: [u8; 16],
     index: u16,
     pc: u16,
-    pub display: [bool; 64*32],
+    /// Pixels of the current resolution, row by row with a stride of `width()`.
+    /// Bit N of a pixel is set if it is lit on plane N.
+    pub display: [u8; DISPLAY_WIDTH*DISPLAY_HEIGHT],
+    hires: bool,
     stack: [u16; 16],
     sp: u16,
     opcode: u16,
     delay_timer: u8,
     sound_timer: u8,
     pub draw_flag: bool,
+    pub error_mode: ErrorMode,
+    pub quirks: Quirks,
+    mode: Mode,
+    //XO-CHIP planes affected by drawing, one bit per plane
+    planes: u8,
+    //XO-CHIP audio pattern buffer, once loaded by F002, and playback pitch
+    pattern: Option<[u8; 16]>,
+    pitch: u8,
+    //SUPER-CHIP RPL user flags, saved and restored by FX75/FX85
+    rpl: [u8; 16],
+    exited: bool,
+    key_wait: Option<KeyWait>,
+    halted: Option<Chip8Error>,
+    //set by DXYN when the display wait quirk is enabled, cleared by the next timer tick
+    vblank_wait: bool,
+    //random numbers of CXNN, part of the save state
+    rng: Box<dyn Rng>,
+    /// Checked after every instruction, see `last_hit`.
+    pub breakpoints: Breakpoints,
+    hit: Option<Hit>,
+    //memory accessed by the current instruction, only recorded while there are breakpoints
+    accesses: Vec<(usize, bool)>,
+    /// What is recorded for `take_records`.
+    pub tracing: Tracing,
+    records: Vec<Record>,
+    //run_cycle calls so far
+    cycles: u64,
+    //cycles that ran an instruction instead of waiting
+    instructions: u64,
+    //hash of the screen for steps, None once something was drawn
+    screen_hash: Option<u64>,
+}
+
+/// Instruction set understood by the interpreter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The original CHIP-8 instructions.
+    Chip8,
+    /// CHIP-8 plus the SUPER-CHIP 1.1 extensions.
+    SuperChip,
+    /// SUPER-CHIP plus the XO-CHIP extensions of Octo.
+    XoChip,
+}
+
+impl Mode {
+    /// Size of the address space in bytes.
+    pub fn memory_size(&self) -> usize {
+        match *self {
+            Mode::Chip8 | Mode::SuperChip => 0x1000,
+            Mode::XoChip => 0x10000,
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mode, String> {
+        match s {
+            "chip8" => Ok(Mode::Chip8),
+            "schip" | "superchip" => Ok(Mode::SuperChip),
+            "xochip" => Ok(Mode::XoChip),
+            _ => Err(format!("unknown mode '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mode::Chip8 => write!(f, "chip8"),
+            Mode::SuperChip => write!(f, "schip"),
+            Mode::XoChip => write!(f, "xochip"),
+        }
+    }
+}
+
+/// Progress of an FX0A instruction waiting for a key.
+///
+/// Like on the COSMAC VIP, the key is only accepted once it is released again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyWait {
+    //waiting for any key to be pressed, V[X] receives the key
+    Press { x: usize },
+    //waiting for `key` to be released
+    Release { x: usize, key: u8 },
 }
 
 const FONT_SET:  [u8; 80] = [
@@ -37,77 +160,534 @@ const FONT_SET:  [u8; 80] = [
     0xF0,0x80,0xF0,0x80,0x80, //F
     ];
 
+const BIG_FONT_SET: [u8; 160] = [
+    0x3C,0x7E,0xE7,0xC3,0xC3,0xC3,0xC3,0xE7,0x7E,0x3C, //0
+    0x18,0x38,0x58,0x18,0x18,0x18,0x18,0x18,0x18,0x3C, //1
+    0x3E,0x7F,0xC3,0x06,0x0C,0x18,0x30,0x60,0xFF,0xFF, //2
+    0x3C,0x7E,0xC3,0x03,0x0E,0x0E,0x03,0xC3,0x7E,0x3C, //3
+    0x06,0x0E,0x1E,0x36,0x66,0xC6,0xFF,0xFF,0x06,0x06, //4
+    0xFF,0xFF,0xC0,0xC0,0xFC,0xFE,0x03,0xC3,0x7E,0x3C, //5
+    0x3E,0x7C,0xE0,0xC0,0xFC,0xFE,0xC3,0xC3,0x7E,0x3C, //6
+    0xFF,0xFF,0x03,0x06,0x0C,0x18,0x30,0x60,0x60,0x60, //7
+    0x3C,0x7E,0xC3,0xC3,0x7E,0x7E,0xC3,0xC3,0x7E,0x3C, //8
+    0x3C,0x7E,0xC3,0xC3,0x7F,0x3F,0x03,0x03,0x3E,0x7C, //9
+    0x7E,0xFF,0xC3,0xC3,0xC3,0xFF,0xFF,0xC3,0xC3,0xC3, //A
+    0xFC,0xFC,0xC3,0xC3,0xFC,0xFC,0xC3,0xC3,0xFC,0xFC, //B
+    0x3C,0xFF,0xC3,0xC0,0xC0,0xC0,0xC0,0xC3,0xFF,0x3C, //C
+    0xFC,0xFE,0xC3,0xC3,0xC3,0xC3,0xC3,0xC3,0xFE,0xFC, //D
+    0xFF,0xFF,0xC0,0xC0,0xFF,0xFF,0xC0,0xC0,0xFF,0xFF, //E
+    0xFF,0xFF,0xC0,0xC0,0xFF,0xFF,0xC0,0xC0,0xC0,0xC0, //F
+    ];
+
 impl Chip8 {
-    pub fn new(op_code: Vec<u8>, is_test: bool) ->  Chip8 {
-        let mut memory: [u8; 4096] = [0; 4096];
+    /// Creates an interpreter for `mode` with the ROM loaded at 0x200.
+    pub fn with_mode(op_code: Vec<u8>, mode: Mode) -> Result<Chip8, Chip8Error> {
+        let mut memory = vec![0; mode.memory_size()];
+
+        if op_code.len() > memory.len() - 0x200 {
+            return Err(Chip8Error::RomTooLarge { size: op_code.len(), max: memory.len() - 0x200 });
+        }
         
-        if !is_test {
-            for (i, byte) in op_code.iter().enumerate() {
-                memory[0x200 + i] = byte.clone();
-            }
+        for (i, byte) in op_code.iter().enumerate() {
+            memory[0x200 + i] = *byte;
+        }
 
-            for (i, byte) in FONT_SET.iter().enumerate() {
-                memory[i]  = byte.clone();
-            }
+        for (i, byte) in FONT_SET.iter().enumerate() {
+            memory[i]  = *byte;
         }
-        else {
-           for (i, byte) in FONT_SET.iter().enumerate() {
-               memory[i]  = byte.clone();
-           }
 
-           memory[0x200 ] = 0xD1;
-           memory[0x200 + 1] = 0x25;
-           memory[0x200 + 2] = 0xA0;
-           memory[0x200 + 3] = 0x05;
-           memory[0x200 + 4] = 0x61;
-           memory[0x200 + 5] = 0x05;
-           memory[0x200 + 6] = 0xD1;
-           memory[0x200 + 7] = 0x25;
+        for (i, byte) in BIG_FONT_SET.iter().enumerate() {
+            memory[BIG_FONT_ADDRESS + i] = *byte;
         }
 
-        Chip8 {
+        Ok(Chip8 {
             memory,
             register: [0; 16],
             index: 0,
             pc: 0x200,
-            display: [false; 64*32],
+            display: [0; DISPLAY_WIDTH*DISPLAY_HEIGHT],
+            hires: false,
             stack: [0; 16],
             sp: 0,
             opcode: 0,
             delay_timer: 0,
             sound_timer: 0,
             draw_flag: false,
-        }
+            error_mode: ErrorMode::Lenient,
+            quirks: Quirks::default(),
+            mode,
+            planes: 1,
+            pattern: None,
+            pitch: 64,
+            rpl: [0; 16],
+            exited: false,
+            key_wait: None,
+            halted: None,
+            vblank_wait: false,
+            rng: Box::new(Xorshift::new(DEFAULT_SEED)),
+            breakpoints: Breakpoints::new(),
+            hit: None,
+            accesses: Vec::new(),
+            tracing: Tracing::new(),
+            records: Vec::new(),
+            cycles: 0,
+            instructions: 0,
+            screen_hash: None,
+        })
     }
     
-    pub fn run_cycle(&mut self, key: u8, event_pump: &mut sdl2::EventPump) {
-            self.fetch_opcode();
-            if self.opcode != 0 {
-                //println!("V[1]: {}, V[2]: {}", self.register[1],self.register[2]);
-                if self.draw_flag {
-                    let mut i = 0;
-                    while i < 64*32 {
-                        if self.display[i] == true {
-                            //println!("display=true at: {}", i) 
-                        }
-                        i += 1;
+    /// Executes a single instruction.
+    ///
+    /// Timers are not touched here, see `tick_timers`.
+    /// On error the instruction is skipped or the machine halts, depending on `error_mode`.
+    /// A breakpoint hit by the instruction is reported by `last_hit`.
+    pub fn run_cycle<I: Input>(&mut self, input: &I) -> Result<(), Chip8Error> {
+            self.hit = None;
+            self.cycles += 1;
+            if let Some(ref error) = self.halted {
+                return Err(error.clone());
+            }
+            if self.key_wait.is_some() {
+                self.poll_key_wait(input);
+                return Ok(());
+            }
+            if self.vblank_wait || self.exited {
+                return Ok(());
+            }
+            self.instructions += 1;
+            let pc = self.pc;
+            if self.tracing.traces(self.cycles, pc) {
+                self.trace_step();
+            }
+            let watching = !self.breakpoints.is_empty();
+            if watching {
+                //the breakpoints look at the machine they belong to
+                let mut breakpoints = mem::replace(&mut self.breakpoints, Breakpoints::new());
+                breakpoints.before_cycle(self);
+                self.breakpoints = breakpoints;
+                self.accesses.clear();
+            }
+            let result = match self.fetch_opcode() {
+                Ok(()) if self.opcode != 0 => self.run_opcode(input),
+                other => other,
+            };
+            if let Err(ref error) = result {
+                match self.error_mode {
+                    ErrorMode::Strict => {
+                        self.pc = pc;
+                        self.halted = Some(error.clone());
+                        self.log(Level::Error, Target::Cpu, || format!("{}, halted", error));
+                    }
+                    ErrorMode::Lenient => {
+                        self.pc = pc;
+                        self.advance_pc(2);
+                        self.log(Level::Warn, Target::Cpu, || format!("{}, skipped", error));
                     }
-                    self.draw_flag = false;
                 }
-                println!("{:X} {}", self.opcode, self.delay_timer);
-                self.run_opcode(key, event_pump);
-                if self.delay_timer != 0 {
-                    self.delay_timer -= 1;
+            }
+            if watching {
+                self.hit = self.breakpoints.after_cycle(self, &self.accesses);
+            }
+            result
+    }
+
+    /// Returns the number of `run_cycle` calls since the machine was created.
+    ///
+    /// Steps and messages carry the number of the cycle they were recorded in, starting at 1.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Returns the number of instructions run since the machine was created,
+    /// cycles spent waiting for a key or the vertical blank are not counted.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Takes the messages and steps recorded since the last call, see `tracing`.
+    pub fn take_records(&mut self) -> Vec<Record> {
+        mem::take(&mut self.records)
+    }
+
+    //records a message if `tracing` asks for it, `text` is only built then
+    fn log<F: FnOnce() -> String>(&mut self, level: Level, target: Target, text: F) {
+        if self.tracing.is_enabled(target, level) {
+            let message = Message { cycle: self.cycles, level, target, text: text() };
+            self.records.push(Record::Message(message));
+        }
+    }
+
+    fn trace_step(&mut self) {
+        //the words at pc, wrapping around like the fetch
+        let word = |offset: usize| {
+            (self.memory[self.pc_after(offset) as usize] as u16) << 8 | self.memory[self.pc_after(offset + 1) as usize] as u16
+        };
+        let opcode = word(0);
+        let operand = if opcode == 0xF000 { word(2) } else { 0 };
+        //hashing the screen for every step would be slow, it rarely changes
+        let screen = match self.screen_hash {
+            Some(hash) => hash,
+            None => rom_hash(self.screen()),
+        };
+        self.screen_hash = Some(screen);
+        let step = Step {
+            cycle: self.cycles,
+            pc: self.pc,
+            opcode,
+            operand,
+            registers: Some(self.register),
+            index: Some(self.index),
+            sp: Some(self.sp as u8),
+            delay_timer: Some(self.delay_timer),
+            sound_timer: Some(self.sound_timer),
+            screen: Some(screen),
+        };
+        self.records.push(Record::Step(step));
+    }
+
+    fn screen_changed(&mut self) {
+        self.draw_flag = true;
+        self.screen_hash = None;
+    }
+
+    /// Returns the breakpoint hit by the last `run_cycle`, if any.
+    pub fn last_hit(&self) -> Option<&Hit> {
+        self.hit.as_ref()
+    }
+
+    /// Runs at most `cycles` instructions until a breakpoint is hit or the program exits.
+    ///
+    /// Timers are not touched, like in `run_cycle`. Returns the first error.
+    pub fn run_until<I: Input>(&mut self, input: &I, cycles: u64) -> Result<Stop, Chip8Error> {
+        for _ in 0..cycles {
+            self.run_cycle(input)?;
+            if let Some(hit) = self.hit {
+                return Ok(Stop::Hit(hit));
+            }
+            if self.exited {
+                return Ok(Stop::Exited);
+            }
+        }
+        Ok(Stop::Limit)
+    }
+
+    /// Runs one 60 Hz frame: `cycles` instructions followed by a timer tick.
+    ///
+    /// The frame ends early at the first failing instruction.
+    pub fn run_frame<I: Input>(&mut self, input: &I, cycles: u32) -> Result<(), Chip8Error> {
+        let mut result = Ok(());
+        for _ in 0..cycles {
+            result = self.run_cycle(input);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.tick_timers();
+        result
+    }
+
+    /// Returns true once the program has exited with 00FD.
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Width of the current resolution in pixels.
+    pub fn width(&self) -> usize {
+        if self.hires { DISPLAY_WIDTH } else { DISPLAY_WIDTH / 2 }
+    }
+
+    /// Height of the current resolution in pixels.
+    pub fn height(&self) -> usize {
+        if self.hires { DISPLAY_HEIGHT } else { DISPLAY_HEIGHT / 2 }
+    }
+
+    /// Returns the pixels of the current resolution, `width()` pixels per row.
+    /// Each pixel is a palette index with one bit per plane.
+    pub fn screen(&self) -> &[u8] {
+        &self.display[..self.width() * self.height()]
+    }
+
+    /// Returns the XO-CHIP audio pattern to play instead of the buzzer, if any.
+    pub fn audio_pattern(&self) -> Option<Pattern> {
+        self.pattern.map(|bits| Pattern { bits, pitch: self.pitch })
+    }
+
+    /// Returns the address of the next instruction.
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Returns the address `bytes` after pc, wrapping around at the end of memory like pc does.
+    pub fn pc_after(&self, bytes: usize) -> u16 {
+        ((self.pc as usize + bytes) % self.memory.len()) as u16
+    }
+
+    /// Returns the index register I.
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    /// Returns the registers V[0] to V[F].
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.register
+    }
+
+    /// Returns the return addresses on the stack, oldest first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    /// Returns the whole address space.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Returns the whole address space for editing, e.g. by a debugger.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// Returns true while FX0A waits for a key press and release.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Returns the error that halted the machine in strict mode, if any.
+    pub fn halted(&self) -> Option<&Chip8Error> {
+        self.halted.as_ref()
+    }
+
+    /// Decrements the delay and sound timers, meant to be called at 60 Hz.
+    pub fn tick_timers(&mut self) {
+        self.vblank_wait = false;
+        if self.delay_timer != 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer != 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    /// Returns true while the buzzer should sound, i.e. the sound timer is non-zero.
+    pub fn is_sound_playing(&self) -> bool {
+        self.sound_timer != 0
+    }
+
+    /// Replaces the generator of CXNN, e.g. with `Xorshift::new(seed)`.
+    ///
+    /// A new machine starts with `Xorshift::new(DEFAULT_SEED)`.
+    pub fn set_rng(&mut self, rng: Box<dyn Rng>) {
+        self.rng = rng;
+    }
+
+    /// Serializes the whole machine together with the held keys.
+    ///
+    /// `quirks` and `error_mode` are configuration and not part of the state.
+    pub fn save_state(&self, keypad: &Keypad) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.u8(match self.mode {
+            Mode::Chip8 => 0,
+            Mode::SuperChip => 1,
+            Mode::XoChip => 2,
+        });
+        writer.bytes(&self.memory);
+        writer.bytes(&self.register);
+        writer.u16(self.index);
+        writer.u16(self.pc);
+        writer.bytes(&self.display);
+        writer.bool(self.hires);
+        for address in self.stack.iter() {
+            writer.u16(*address);
+        }
+        writer.u16(self.sp);
+        writer.u16(self.opcode);
+        writer.u8(self.delay_timer);
+        writer.u8(self.sound_timer);
+        writer.u8(self.planes);
+        match self.pattern {
+            Some(bits) => {
+                writer.bool(true);
+                writer.bytes(&bits);
+            }
+            None => writer.bool(false),
+        }
+        writer.u8(self.pitch);
+        writer.bytes(&self.rpl);
+        writer.bool(self.exited);
+        match self.key_wait {
+            None => writer.bytes(&[0, 0, 0]),
+            Some(KeyWait::Press { x }) => writer.bytes(&[1, x as u8, 0]),
+            Some(KeyWait::Release { x, key }) => writer.bytes(&[2, x as u8, key]),
+        }
+        writer.bool(self.vblank_wait);
+        writer.bytes(&self.rng.tag());
+        writer.u64(self.rng.state());
+        writer.u16(keypad.bits());
+        writer.data
+    }
+
+    /// Restores a state written by `save_state` and returns the keys held at that time.
+    ///
+    /// The machine is left untouched if the state is invalid.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<Keypad, StateError> {
+        let mut reader = StateReader::new(data)?;
+        let mode = match reader.u8()? {
+            0 => Mode::Chip8,
+            1 => Mode::SuperChip,
+            2 => Mode::XoChip,
+            _ => return Err(StateError::InvalidField("mode")),
+        };
+        let memory = reader.bytes(mode.memory_size())?;
+        let register = reader.bytes(16)?;
+        let index = reader.u16()?;
+        let pc = reader.u16()?;
+        let display = reader.bytes(DISPLAY_WIDTH * DISPLAY_HEIGHT)?;
+        let hires = reader.bool("resolution")?;
+        let mut stack = [0; 16];
+        for address in stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        let sp = reader.u16()?;
+        if sp as usize > stack.len() {
+            return Err(StateError::InvalidField("stack pointer"));
+        }
+        let opcode = reader.u16()?;
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let planes = reader.u8()?;
+        if planes as usize >= 1 << PLANES {
+            return Err(StateError::InvalidField("plane selection"));
+        }
+        let pattern = if reader.bool("audio pattern")? {
+            let mut bits = [0; 16];
+            bits.copy_from_slice(reader.bytes(16)?);
+            Some(bits)
+        } else {
+            None
+        };
+        let pitch = reader.u8()?;
+        let rpl = reader.bytes(16)?;
+        let exited = reader.bool("exit flag")?;
+        let key_wait = match (reader.u8()?, reader.u8()? as usize, reader.u8()?) {
+            (0, _, _) => None,
+            (1, x, _) if x < 16 => Some(KeyWait::Press { x }),
+            (2, x, key) if x < 16 && key < 16 => Some(KeyWait::Release { x, key }),
+            _ => return Err(StateError::InvalidField("key wait")),
+        };
+        let vblank_wait = reader.bool("display wait")?;
+        let mut rng = self.rng.clone();
+        if reader.bytes(4)? != rng.tag() {
+            return Err(StateError::RngMismatch);
+        }
+        if !rng.set_state(reader.u64()?) {
+            return Err(StateError::InvalidField("random number state"));
+        }
+        let keypad = Keypad::from_bits(reader.u16()?);
+        reader.finish()?;
+
+        self.mode = mode;
+        self.memory = memory.to_vec();
+        self.register.copy_from_slice(register);
+        self.index = index;
+        self.pc = pc;
+        self.display.copy_from_slice(display);
+        self.hires = hires;
+        self.stack = stack;
+        self.sp = sp;
+        self.opcode = opcode;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.planes = planes;
+        self.pattern = pattern;
+        self.pitch = pitch;
+        self.rpl.copy_from_slice(rpl);
+        self.exited = exited;
+        self.key_wait = key_wait;
+        self.vblank_wait = vblank_wait;
+        self.rng = rng;
+        self.halted = None;
+        self.screen_changed();
+        Ok(keypad)
+    }
+
+    fn poll_key_wait<I: Input>(&mut self, input: &I) {
+        match self.key_wait {
+            Some(KeyWait::Press { x }) => {
+                if let Some(key) = (0..16).find(|&key| input.is_pressed(key)) {
+                    self.key_wait = Some(KeyWait::Release { x, key });
+                    self.log(Level::Debug, Target::Input, || format!("key {:X} pressed, waiting for its release", key));
                 }
             }
+            Some(KeyWait::Release { x, key }) if !input.is_pressed(key) => {
+                self.register[x] = key;
+                self.key_wait = None;
+                self.log(Level::Debug, Target::Input, || format!("key {:X} released into V{:X}", key, x));
+            }
+            _ => {}
+        }
     }
     
-    fn fetch_opcode(&mut self) {
-        self.opcode = ((self.memory[self.pc as usize] as u16) << 8) 
-        | self.memory[(self.pc + 1) as usize] as u16;
+    fn fetch_opcode(&mut self) -> Result<(), Chip8Error> {
+        self.opcode = ((self.fetch_byte(self.pc as usize)? as u16) << 8)
+        | self.fetch_byte(self.pc_after(1) as usize)? as u16;
+        Ok(())
+    }
+
+    fn fetch_byte(&self, address: usize) -> Result<u8, Chip8Error> {
+        //instruction fetches do not trigger read watchpoints
+        match self.memory.get(address) {
+            Some(&byte) => Ok(byte),
+            None => Err(Chip8Error::MemoryOutOfBounds { address, pc: self.pc }),
+        }
+    }
+
+    fn read_memory(&mut self, address: usize) -> Result<u8, Chip8Error> {
+        if !self.breakpoints.is_empty() {
+            self.accesses.push((address, false));
+        }
+        self.fetch_byte(address)
+    }
+
+    fn write_memory(&mut self, address: usize, byte: u8) -> Result<(), Chip8Error> {
+        if !self.breakpoints.is_empty() {
+            self.accesses.push((address, true));
+        }
+        let pc = self.pc;
+        match self.memory.get_mut(address) {
+            Some(cell) => {
+                *cell = byte;
+                Ok(())
+            }
+            None => Err(Chip8Error::MemoryOutOfBounds { address, pc }),
+        }
+    }
+
+    fn skip_next(&mut self) {
+        //skips the next instruction, F000 NNNN of XO-CHIP is 4 bytes long
+        let long = self.mode == Mode::XoChip
+            && self.memory[self.pc_after(2) as usize] == 0xF0
+            && self.memory[self.pc_after(3) as usize] == 0x00;
+        self.advance_pc(if long { 6 } else { 4 });
+    }
+
+    //moves pc forward, wrapping around at the end of memory like the address bus
+    fn advance_pc(&mut self, bytes: usize) {
+        self.pc = self.pc_after(bytes);
+    }
+
+    fn invalid_opcode(&self) -> Chip8Error {
+        Chip8Error::InvalidOpcode { opcode: self.opcode, pc: self.pc }
     }
     
-    fn run_opcode(&mut self, key: u8, event_pump: &mut sdl2::EventPump) {
+    fn run_opcode<I: Input>(&mut self, input: &I) -> Result<(), Chip8Error> {
         match self.opcode & 0xF000 {
             0x0000 => self.op_0xxx(),
             0x1000 => self.op_1xxx(),
@@ -123,431 +703,772 @@ impl Chip8 {
             0xB000 => self.op_bxxx(),
             0xC000 => self.op_cxxx(),
             0xD000 => self.op_dxxx(),
-            0xE000 => self.op_exxx(key),
-            0xF000 => self.op_fxxx(event_pump),
-            _ => {
-                println!("opcode: {:X},not implemented yet", self.opcode);
-                self.pc += 2;
-            }
+            0xE000 => self.op_exxx(input),
+            0xF000 => self.op_fxxx(),
+            _ => Err(self.invalid_opcode()),
         }
     }
  
-    fn op_0xxx(&mut self) {
-        match self.opcode & 0xFF00 {
-            0x0000 => self.op_00xx(),
-            _ => {
-                println!("{} not implemented yet!!!", self.opcode);
-                self.pc += 2;
-            }
+    fn op_0xxx(&mut self) -> Result<(), Chip8Error> {
+        match self.opcode {
+            0x00E0 | 0x00EE => self.op_00xx(),
+            _ if self.mode == Mode::Chip8 => self.op_0nnn(),
+            _ if self.opcode & 0xFF00 == 0x0000 => self.op_00xx(),
+            _ => Err(self.invalid_opcode()),
+        }
+    }
+
+    fn op_0nnn(&mut self) -> Result<(), Chip8Error> {
+        //0NNN: run the CDP1802 machine code subroutine at NNN
+        //      the routine sees the memory layout of the COSMAC VIP interpreter:
+        //      V[0] to V[F] at 0xEF0, the display at 0xF00, I in R(A), the timers in R(8),
+        //      the CHIP-8 pc in R(5) and the stack pointer in R(2)
+        //      it returns to the interpreter with SEP R4 (D4)
+        for x in 0..16 {
+            self.memory[VIP_REGISTERS + x] = self.register[x];
+        }
+        let display = &self.display;
+        for (i, byte) in self.memory[VIP_DISPLAY..VIP_DISPLAY + 256].iter_mut().enumerate() {
+            *byte = (0..8).fold(0, |byte, bit| (byte << 1) | (display[i * 8 + bit] & 0x01));
+        }
+
+        let mut cpu = Cdp1802::new();
+        cpu.r[2] = VIP_STACK;
+        cpu.x = 2;
+        cpu.r[3] = self.opcode & 0x0FFF;
+        cpu.p = 3;
+        cpu.r[5] = self.pc_after(2);
+        cpu.r[8] = ((self.delay_timer as u16) << 8) | self.sound_timer as u16;
+        cpu.r[0xA] = self.index;
+        cpu.r[0xB] = VIP_DISPLAY as u16;
+        let routine = self.opcode & 0x0FFF;
+        match cpu.run_until_sep(&mut VipBus(self), 4, MACHINE_CODE_MAX_STEPS) {
+            Ok(steps) => self.log(Level::Debug, Target::MachineCode, || format!("routine {:03X} returned after {} instructions", routine, steps)),
+            Err(error) => return Err(Chip8Error::MachineCode { pc: self.pc, error }),
+        }
+
+        for x in 0..16 {
+            self.register[x] = self.memory[VIP_REGISTERS + x];
         }
+        for i in 0..64*32 {
+            let bit = self.memory[VIP_DISPLAY + i / 8] & (0x80 >> (i % 8)) != 0;
+            self.display[i] = (self.display[i] & !0x01) | bit as u8;
+        }
+        self.delay_timer = (cpu.r[8] >> 8) as u8;
+        self.sound_timer = cpu.r[8] as u8;
+        self.index = cpu.r[0xA];
+        self.pc = cpu.r[5] & 0x0FFF;
+        self.screen_changed();
+        Ok(())
     }
 
-    fn op_00xx(&mut self) {
+    fn op_00xx(&mut self) -> Result<(), Chip8Error> {
+        let schip = self.mode != Mode::Chip8;
         match self.opcode & 0x00FF {
             0x00E0 => {
-                //00E0: clear the display
-                self.display = [false; 64*32];
-                self.pc += 2;
+                //00E0: clear the display, only the selected planes on XO-CHIP
+                for pixel in self.display.iter_mut() {
+                    *pixel &= !self.planes;
+                }
+                self.screen_changed();
+                self.advance_pc(2);
+            }
+
+            n @ 0x00C0 ..= 0x00CF if schip => {
+                //00CN: scroll the display down by N pixels
+                self.scroll(0, (n & 0x000F) as isize);
+                self.advance_pc(2);
+            }
+
+            0x00FB if schip => {
+                //00FB: scroll the display right by 4 pixels
+                self.scroll(4, 0);
+                self.advance_pc(2);
+            }
+
+            0x00FC if schip => {
+                //00FC: scroll the display left by 4 pixels
+                self.scroll(-4, 0);
+                self.advance_pc(2);
+            }
+
+            0x00FD if schip => {
+                //00FD: exit the interpreter
+                self.exited = true;
+                self.log(Level::Info, Target::Cpu, || String::from("program exited"));
+                self.advance_pc(2);
+            }
+
+            0x00FE | 0x00FF if schip => {
+                //00FE: switch to low resolution (64x32), 00FF: switch to high resolution (128x64)
+                //      the display is cleared as the pixel layout changes
+                self.hires = self.opcode & 0x0001 != 0;
+                self.display = [0; DISPLAY_WIDTH*DISPLAY_HEIGHT];
+                self.screen_changed();
+                let (width, height) = (self.width(), self.height());
+                self.log(Level::Debug, Target::Display, || format!("resolution {}x{}", width, height));
+                self.advance_pc(2);
             }
             
             0x00EE => {
                 //00EE: return from subroutine
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow { pc: self.pc });
+                }
                 self.sp -= 1;
                 self.pc = self.stack[(self.sp) as usize];
-                self.pc += 2;
-            }
-            _ => {
-                println!("{:X} not implemented yet!!!", self.opcode);
-                self.pc += 2;
+                self.advance_pc(2);
             }
-
+            _ => return Err(self.invalid_opcode()),
         }
+        Ok(())
     }
 
-    fn op_1xxx(&mut self) {
+    fn op_1xxx(&mut self) -> Result<(), Chip8Error> {
         //1NNN: Jump to the address NNN
         self.pc = self.opcode & 0x0FFF;
+        Ok(())
     }
     
-    fn op_2xxx(&mut self) {
+    fn op_2xxx(&mut self) -> Result<(), Chip8Error> {
         //2NNN: call subroutine at NNN -> store pc on stack and jump to address NNN
+        if self.sp as usize >= self.stack.len() {
+            return Err(Chip8Error::StackOverflow { pc: self.pc });
+        }
         self.stack[self.sp as usize] = self.pc;
         self.sp += 1;
         self.pc = self.opcode & 0x0FFF;
+        Ok(())
     }
     
-    fn op_3xxx(&mut self) {
+    fn op_3xxx(&mut self) -> Result<(), Chip8Error> {
         //3XNN: skip next instruction if V[X] == NN
         let x: u16 = self.register[((self.opcode & 0x0F00) >> 8) as usize] as u16;
-        let y: u16 = (self.opcode & 0x00FF) as u16;
+        let y: u16 = self.opcode & 0x00FF;
         if x == y {
-            self.pc += 4; 
+            self.skip_next();
         }
         else {
-            self.pc += 2;
+            self.advance_pc(2);
         }
+        Ok(())
     }
     
-    fn op_4xxx(&mut self) {
+    fn op_4xxx(&mut self) -> Result<(), Chip8Error> {
         //4XNN: skip the next instruction if V[X] != NN
         let x: u16 = self.register[((self.opcode & 0x0F00) >> 8) as usize] as u16;
-        let y: u16 = (self.opcode & 0x00FF) as u16;
+        let y: u16 = self.opcode & 0x00FF;
         if x != y {
-            self.pc += 4;
+            self.skip_next();
         }
         else {
-            self.pc += 2;
+            self.advance_pc(2);
         }
+        Ok(())
     }
     
-    fn op_5xxx(&mut self) {
-        //5XY0: skip thenext instruction if V[X] == V[Y] 
-        let x: u16 = self.register[((self.opcode & 0x0F00) >> 8) as usize] as u16;
-        let y: u16 = self.register[((self.opcode & 0x00F0) >> 4) as usize] as u16;
-        if x == y {
-            self.pc += 4; 
-        }
-        else {
-            self.pc += 2;
+    fn op_5xxx(&mut self) -> Result<(), Chip8Error> {
+        let x = ((self.opcode & 0x0F00) >> 8) as usize;
+        let y = ((self.opcode & 0x00F0) >> 4) as usize;
+        match self.opcode & 0x000F {
+            0x0000 => {
+                //5XY0: skip thenext instruction if V[X] == V[Y] 
+                if self.register[x] == self.register[y] {
+                    self.skip_next();
+                }
+                else {
+                    self.advance_pc(2);
+                }
+            }
+
+            0x0002 if self.mode == Mode::XoChip => {
+                //5XY2: store V[X] to V[Y] in memory starting with I, I is not changed
+                //      the registers are stored in reverse order if X > Y
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.write_memory(self.index as usize + offset, self.register[register])?;
+                }
+                self.advance_pc(2);
+            }
+
+            0x0003 if self.mode == Mode::XoChip => {
+                //5XY3: load V[X] to V[Y] from memory starting with I, I is not changed
+                for (offset, register) in register_range(x, y).enumerate() {
+                    self.register[register] = self.read_memory(self.index as usize + offset)?;
+                }
+                self.advance_pc(2);
+            }
+            _ => return Err(self.invalid_opcode()),
         }
+        Ok(())
     }
     
-    fn op_6xxx(&mut self) {
+    fn op_6xxx(&mut self) -> Result<(), Chip8Error> {
         //6XNN: sets V[X] to NN
         self.register[((self.opcode & 0x0F00) >> 8) as usize] = (self.opcode & 0x00FF) as u8;
-        self.pc += 2; 
+        self.advance_pc(2);
+        Ok(())
     }
     
-    fn op_7xxx(&mut self) {
+    fn op_7xxx(&mut self) -> Result<(), Chip8Error> {
         //7XNN: add NN to V[X]
-        self.register[((self.opcode & 0x0F00) >> 8) as usize] += (self.opcode & 0x00FF) as u8;
-        self.pc += 2;
+        //the carry is discarded, V[F] is not affected
+        let x = ((self.opcode & 0x0F00) >> 8) as usize;
+        self.register[x] = self.register[x].wrapping_add((self.opcode & 0x00FF) as u8);
+        self.advance_pc(2);
+        Ok(())
     }
     
-    fn op_8xxx(&mut self) {
+    fn op_8xxx(&mut self) -> Result<(), Chip8Error> {
         match self.opcode & 0x000F {
             0x0000 => {
                 //8XY0: set V[X] = V[Y]
                 self.register[((self.opcode & 0x0F00) >> 8) as usize] =
                     self.register[((self.opcode & 0x00F0) >> 4) as usize];
-                self.pc += 2;
+                self.advance_pc(2);
             } 
             
             0x0001 => {
                 //8XY1: set V[X] = (V[X] or V[Y])
                 self.register[((self.opcode & 0x0F00) >> 8) as usize] |=
                     self.register[((self.opcode & 0x00F0) >>  4) as usize];
-                self.pc += 2;
+                if self.quirks.vf_reset {
+                    self.register[15] = 0;
+                }
+                self.advance_pc(2);
             }
             
             0x0002 => {
                 //8XY2: set V[X] = (V[X] and V[Y])
                 self.register[((self.opcode & 0x0F00) >> 8) as usize] &=
                     self.register[((self.opcode & 0x00F0) >>  4) as usize];
-                self.pc += 2;
+                if self.quirks.vf_reset {
+                    self.register[15] = 0;
+                }
+                self.advance_pc(2);
             }
             
             0x0003 => {
                 //8XY3: set V[X] = (V[X] xor V[Y])
                 self.register[((self.opcode & 0x0F00) >> 8) as usize] ^= 
                     self.register[((self.opcode & 0x00F0) >>  4) as usize];
-                self.pc += 2;
+                if self.quirks.vf_reset {
+                    self.register[15] = 0;
+                }
+                self.advance_pc(2);
             }
             
             0x0004 => {
                 //8XY4: add V[Y] to V[X], if carry set V[F] = 1, if no carry set V[F] = 0
-                let x = (self.opcode & 0x0F00) >> 8;
-                let y = (self.opcode & 0x00F0) >> 4;
-                self.register[15] = 0;
-                let sum = self.register[x as usize] as u16 + self.register[y as usize] as u16;
-                if sum > 0xFF {
-                    self.register[15] = 1;
-                }
-                self.register[x as usize] = sum as u8;
-                self.pc += 2;
+                let (x, y) = self.xy();
+                let (sum, carry) = self.register[x].overflowing_add(self.register[y]);
+                self.arithmetic_result(sum, carry as u8);
+                self.advance_pc(2);
             }
             
             0x0005 => {
                 //8XY5: set V[X] -= V[Y], if borrow set V[F] = 0, else set V[F] = 1
-                let x = (self.opcode & 0x0F00) >> 8;
-                let y = (self.opcode & 0x00F0) >> 4;
-                self.register[15] = 1;
-                if self.register[y as usize] > self.register[x as usize] {
-                    self.register[15] = 0;
-                    self.register[x as usize] = 0;
-                }
-                else {
-                    self.register[x as usize] = self.register[x as usize] - self.register[y as usize];
-                }
-                self.pc += 2;
+                let (x, y) = self.xy();
+                let (difference, borrow) = self.register[x].overflowing_sub(self.register[y]);
+                self.arithmetic_result(difference, !borrow as u8);
+                self.advance_pc(2);
             }
             
             0x0006 => {
                 //8XY6: set V[F] to LSB of V[Y], set V[X] = (V[Y] >> 1)
-                self.register[15] = self.register[((self.opcode & 0x00F0) >> 4) as usize] & 0x1;
-                self.register[((self.opcode & 0x0F00) >> 8) as usize] =
-                    self.register[((self.opcode & 0x00F0) >> 4) as usize] >> 1;
-                self.pc += 2;
+                //      with the shift quirk V[X] is shifted instead of V[Y]
+                let value = self.shift_operand();
+                self.arithmetic_result(value >> 1, value & 0x1);
+                self.advance_pc(2);
             }
             
             0x0007 => {
                 //8XY7: set V[X] = (V[Y] - V[X]), if borrow set V[F] = 0, else set V[F] = 1
-                self.register[15] =
-                    if self.register[((self.opcode & 0x0F00) >> 8) as usize] >
-                        self.register[((self.opcode & 0x00F0) >> 4) as usize]
-                    {1} else {0};
-                self.register[((self.opcode & 0x0F00) >> 8) as usize] =
-                    self.register[((self.opcode & 0x00F0) >> 4) as usize] -
-                        self.register[((self.opcode & 0x0F00) >> 8) as usize];
-                self.pc += 2;
+                let (x, y) = self.xy();
+                let (difference, borrow) = self.register[y].overflowing_sub(self.register[x]);
+                self.arithmetic_result(difference, !borrow as u8);
+                self.advance_pc(2);
             }
             
             0x000E => {
                 //8XYE: set V[F] to MSB of V[Y], set V[X] = (V[Y] << 1)
-                self.register[15] =
-                    if  (self.register[((self.opcode & 0x00F0) >> 4) as usize] as u16 & 0x8000 as u16) > 0
-                    {1} else {0};
-                self.register[((self.opcode & 0x0F00) >> 8) as usize] =
-                    self.register[((self.opcode & 0x00F0) >> 4) as usize] << 1;
-                self.pc += 2;
-            
-            }
-            _ => {
-                println!("opcode: {:X},not implemented yet", self.opcode);
-                self.pc += 2;
+                //      with the shift quirk V[X] is shifted instead of V[Y]
+                let value = self.shift_operand();
+                self.arithmetic_result(value << 1, value >> 7);
+                self.advance_pc(2);
             }
+            _ => return Err(self.invalid_opcode()),
         }
+        Ok(())
     } 
 
-    fn op_9xxx(&mut self) {
+    fn shift_operand(&self) -> u8 {
+        if self.quirks.shift_vx {
+            self.register[((self.opcode & 0x0F00) >> 8) as usize]
+        } else {
+            self.register[((self.opcode & 0x00F0) >> 4) as usize]
+        }
+    }
+
+    //X and Y of an 8XYN opcode
+    fn xy(&self) -> (usize, usize) {
+        (((self.opcode & 0x0F00) >> 8) as usize, ((self.opcode & 0x00F0) >> 4) as usize)
+    }
+
+    //writes the result of 8XY4 to 8XYE to V[X], then the flag to V[F] so that it wins for X = F
+    fn arithmetic_result(&mut self, result: u8, flag: u8) {
+        self.register[self.xy().0] = result;
+        self.register[15] = flag;
+    }
+
+    fn op_9xxx(&mut self) -> Result<(), Chip8Error> {
         //9XY0: skip the next instruction if V[X] != V[Y]
         let x: u16 = self.register[((self.opcode & 0x0F00) >> 8) as usize] as u16;
         let y: u16 = self.register[((self.opcode & 0x00F0) >> 4) as usize] as u16;
         if x != y {
-            self.pc += 4;
+            self.skip_next();
         }
         else {
-            self.pc += 2;
+            self.advance_pc(2);
         }
+        Ok(())
     }
     
-    fn op_axxx(&mut self) {
+    fn op_axxx(&mut self) -> Result<(), Chip8Error> {
         //ANNN: sets the index to the adress NNN
         self.index = self.opcode & 0x0FFF;
-        self.pc += 2;
+        self.advance_pc(2);
+        Ok(())
     }
     
-    fn op_bxxx(&mut self) {
+    fn op_bxxx(&mut self) -> Result<(), Chip8Error> {
         //BNNN: jump to the address V[0] + NNN
-        self.pc = self.register[0] as u16 + self.opcode & 0x0FFF;
+        //      with the jump quirk this is BXNN: jump to the address V[X] + XNN
+        let offset = if self.quirks.jump_vx {
+            self.register[((self.opcode & 0x0F00) >> 8) as usize]
+        } else {
+            self.register[0]
+        };
+        self.pc = (offset as u16 + self.opcode) & 0x0FFF;
+        Ok(())
     }
     
-    fn op_cxxx(&mut self) {
+    fn op_cxxx(&mut self) -> Result<(), Chip8Error> {
         //CXNN: set V[X] to random u8 and NN
-        let r = rand::random::<(u8)>();
+        let r = self.rng.next_byte();
         let n = (self.opcode & 0x00FF) as u8;
         self.register[((self.opcode & 0x0F00) >> 8) as usize] = r & n;
-        self.pc += 2;
+        self.advance_pc(2);
+        Ok(())
     }
     
-    fn op_dxxx(&mut self) {
+    fn op_dxxx(&mut self) -> Result<(), Chip8Error> {
         //DXYN: draw sprite at coordinate (V[X],V[Y]) 
         //      with a width of 8 pixels and a hight of N pixels
-        let x = self.register[((self.opcode & 0x0F00) >> 8) as usize] as u16;
-        let y = self.register[((self.opcode & 0x00F0) >> 4) as usize] as u16;
-        let hight = self.opcode & 0x000F;
-        let mut font_row: u8;
+        //      the start coordinate wraps around, pixels past the edges are
+        //      clipped or wrapped depending on the sprite wrapping quirk
+        //DXY0: (SUPER-CHIP) draw a 16x16 sprite, 8x16 in low resolution
+        //      (XO-CHIP) draw a 16x16 sprite in both resolutions
+        //on XO-CHIP the sprite is drawn on every selected plane, the sprite
+        //data of the planes follows each other in memory
+        let x = self.register[((self.opcode & 0x0F00) >> 8) as usize] as usize % self.width();
+        let y = self.register[((self.opcode & 0x00F0) >> 4) as usize] as usize % self.height();
+        let (hight, columns) = match self.opcode & 0x000F {
+            0 if self.mode == Mode::SuperChip && !self.hires => (16, 8),
+            0 if self.mode != Mode::Chip8 => (16, 16),
+            n => (n as usize, 8),
+        };
+        let sprite_size = hight * columns / 8;
+        let mut address = self.index as usize;
+        let mut collisions = 0;
+
+        for plane in 0..PLANES {
+            if self.planes & (1 << plane) != 0 {
+                collisions = collisions.max(self.draw_sprite(1 << plane, address, x, y, hight, columns)?);
+                address += sprite_size;
+            }
+        }
+        //in hi-res mode SUPER-CHIP reports the number of colliding rows
+        self.register[15] = if self.hires && self.mode == Mode::SuperChip {
+            collisions
+        } else {
+            (collisions > 0) as u8
+        };
+        self.screen_changed();
+        self.vblank_wait = self.quirks.display_wait;
+        self.advance_pc(2);
+        Ok(())
+    }
 
-        self.register[15] = 0;
+    fn draw_sprite(&mut self, plane: u8, address: usize, x: usize, y: usize, hight: usize, columns: usize)
+        -> Result<u8, Chip8Error> {
+        //draws the sprite at `address` onto `plane`, returns the number of colliding rows
+        let (width, height) = (self.width(), self.height());
+        let bytes_per_row = columns / 8;
+        let mut collisions = 0;
 
         for row in 0..hight {
-            font_row = self.memory[(self.index + row) as usize];
+            let mut font_row: u16 = 0;
+            for byte in 0..bytes_per_row {
+                font_row = (font_row << 8) | self.read_memory(address + row * bytes_per_row + byte)? as u16;
+            }
+            let py = y + row;
+            if py >= height && !self.quirks.wrap_sprites {
+                //SUPER-CHIP counts rows clipped at the bottom as collisions in hi-res mode
+                if self.hires && self.mode == Mode::SuperChip {
+                    collisions += 1;
+                }
+                continue;
+            }
 
-            for column in 0..8 {
+            let mut collision = false;
+            for column in 0..columns {
+                let px = x + column;
+                if px >= width && !self.quirks.wrap_sprites {
+                    continue;
+                }
+                let pixel = (px % width) + (py % height) * width;
                 //this checks for every column/pixel in this row if it equals 0
-                if font_row & (0x80 >> column) != 0 {
-                    if self.display[(x + column + ((y + row) * 64)) as usize] == true {
-                        self.register[15] = 1;
+                if font_row & (1 << (columns - 1 - column)) != 0 {
+                    if self.display[pixel] & plane != 0 {
+                        collision = true;
                     }
-                    self.display[(x + column + ((y + row) * 64)) as usize] ^= true;
+                    self.display[pixel] ^= plane;
                 }
             }
+            if collision {
+                collisions += 1;
+            }
         }
-        self.draw_flag = true;
-        self.pc +=2;
+        Ok(collisions)
+    }
+
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        //moves the current screen content, pixels moved in from outside are off
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        //only the selected planes move on XO-CHIP
+        let mut scrolled = self.display;
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let moved = if from_x >= 0 && from_x < width && from_y >= 0 && from_y < height {
+                    self.display[(from_y * width + from_x) as usize] & self.planes
+                } else {
+                    0
+                };
+                let pixel = &mut scrolled[(y * width + x) as usize];
+                *pixel = (*pixel & !self.planes) | moved;
+            }
+        }
+        self.display = scrolled;
+        self.screen_changed();
     }
     
-    fn op_exxx(&mut self, key: u8) {
+    fn op_exxx<I: Input>(&mut self, input: &I) -> Result<(), Chip8Error> {
         match self.opcode & 0x00FF {
             0x009E => {
                 //EX9A: skip instruction if pressed key == V[X]
                 let x = self.register[((self.opcode & 0x0F00) >> 8) as usize];
-                if x == key {
-                    self.pc += 4;
+                if input.is_pressed(x) {
+                    self.skip_next();
                 }
                 else {
-                    self.pc += 2;
+                    self.advance_pc(2);
                 }
             }
             0x00A1 => {
                 //EXA1: skip instruction if pressed key != V[X]
                 let x = self.register[((self.opcode & 0x0F00) >> 8) as usize];
-                if x != key {
-                    self.pc += 4;
+                if !input.is_pressed(x) {
+                    self.skip_next();
                 }
                 else {
-                    self.pc += 2;
+                    self.advance_pc(2);
                 }
             }
-            _ => {
-                println!("opcode: {:X}, not implemented yet", self.opcode);
-                self.pc += 2;
-            }
+            _ => return Err(self.invalid_opcode()),
         }
+        Ok(())
     }
 
-    fn op_fxxx(&mut self, event_pump: &mut sdl2::EventPump) {
+    fn op_fxxx(&mut self) -> Result<(), Chip8Error> {
+        let xo = self.mode == Mode::XoChip;
         match self.opcode & 0x00FF {
+            0x0000 if xo && self.opcode == 0xF000 => {
+                //F000 NNNN: set I to the 16 bit address NNNN
+                let high = self.fetch_byte(self.pc_after(2) as usize)?;
+                let low = self.fetch_byte(self.pc_after(3) as usize)?;
+                self.index = ((high as u16) << 8) | low as u16;
+                self.advance_pc(4);
+            }
+
+            0x0001 if xo => {
+                //FN01: select the drawing planes N
+                self.planes = ((self.opcode & 0x0F00) >> 8) as u8 & 0x03;
+                self.advance_pc(2);
+            }
+
+            0x0002 if xo && self.opcode == 0xF002 => {
+                //F002: load the 16 byte audio pattern buffer from memory starting with I
+                let mut pattern = [0; 16];
+                for (i, byte) in pattern.iter_mut().enumerate() {
+                    *byte = self.read_memory(self.index as usize + i)?;
+                }
+                self.pattern = Some(pattern);
+                self.log(Level::Debug, Target::Audio, || format!("audio pattern {:02X?}", pattern));
+                self.advance_pc(2);
+            }
+
             0x0007 => {
                 //FX07:set V[X] to delay_timer
                 self.register[((self.opcode & 0x0F00) >> 8) as usize] = self.delay_timer;
-                self.pc += 2;
+                self.advance_pc(2);
             }
             
             0x000A => {
-                //FX0A: wait for key press, store key in V[X]
-                'fx0a: loop {
-                    for event in event_pump.poll_iter() {
-                        match event {
-                            Event::KeyDown {keycode: Some(Keycode::Num1), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x00;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::Num2), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x01;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::Num3), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x02;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::Num4), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x03;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::Q), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x04;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::W), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x05;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::E), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x06;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::R), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x07;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::A), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x08;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::S), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x09;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::D), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x0A;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::F), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x0B;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::Y), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x0C;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::X), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x0D;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::C), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x0E;
-                                break 'fx0a
-                            }
-                            Event::KeyDown {keycode: Some(Keycode::V), ..} => {
-                                self.register[((self.opcode & 0x0F00) >> 8) as usize] = 0x0F;
-                                break 'fx0a
-                            }
-                            _ => {}
-
-                        }
-                    }
-                } 
+                //FX0A: wait for key press and release, store key in V[X]
+                //      execution is suspended until then, see poll_key_wait
+                let x = ((self.opcode & 0x0F00) >> 8) as usize;
+                self.key_wait = Some(KeyWait::Press { x });
+                self.log(Level::Debug, Target::Input, || format!("waiting for a key for V{:X}", x));
+                self.advance_pc(2);
             }
             
             0x0015 => {
                 //FX15: set delay_timer to V[X]
-                self.delay_timer = self.register[((self.opcode & 0x0F00) >> 8) as usize] as u8;
-                self.pc += 2;
+                self.delay_timer = self.register[((self.opcode & 0x0F00) >> 8) as usize];
+                self.advance_pc(2);
             }
             
             0x0018 => {
                 //FX18: set sound_timer to V[X]
-                self.sound_timer = self.register[((self.opcode & 0x0F00) >> 8) as usize] as u8;
-                self.pc += 2;
+                self.sound_timer = self.register[((self.opcode & 0x0F00) >> 8) as usize];
+                self.advance_pc(2);
             }
             0x001E => {
                 //FX1E: add V[X] to I
-                self.index += self.register[((self.opcode & 0x0F00) >> 8) as usize] as u16;
-                self.pc += 2;
+                self.index = self.index.wrapping_add(self.register[((self.opcode & 0x0F00) >> 8) as usize] as u16);
+                self.advance_pc(2);
             }
 
             0x0029 => {
                 //FX29: set I to the location ofthe sprite for the character in V[X]
                 let sprite: u8 = self.register[((self.opcode & 0x0F00) >> 8) as usize];
-                self.index = (sprite *5) as u16;
-                self.pc += 2;
+                self.index = (sprite & 0x0F) as u16 * 5;
+                self.advance_pc(2);
+            }
+
+            0x003A if xo => {
+                //FX3A: set the audio pattern playback pitch to V[X]
+                self.pitch = self.register[((self.opcode & 0x0F00) >> 8) as usize];
+                let pitch = self.pitch;
+                self.log(Level::Debug, Target::Audio, || format!("pitch {}", pitch));
+                self.advance_pc(2);
+            }
+
+            0x0030 if self.mode != Mode::Chip8 => {
+                //FX30: set I to the location of the big sprite for the character in V[X]
+                let sprite: u8 = self.register[((self.opcode & 0x0F00) >> 8) as usize];
+                self.index = (BIG_FONT_ADDRESS + (sprite & 0x0F) as usize * 10) as u16;
+                self.advance_pc(2);
             }
 
             0x0033 => {
                 //FX33: store the BCD of V[X] in memory as following:
                 //M[I] = V[X](3), M[I+1] = V[X](2), M[I+2] = V[X](1)
-                self.memory[self.index as usize] =
-                    self.register[((self.opcode & 0x0F00) >> 8) as usize] / 100;
-                self.memory[(self.index + 1) as usize] =
-                    (self.register[((self.opcode & 0x0F00) >> 8) as usize] % 100) / 10;
-                self.memory[(self.index + 2) as usize] =
-                    self.register[((self.opcode & 0x0F00) >> 8) as usize] % 10;
-                self.pc += 2;
+                let value = self.register[((self.opcode & 0x0F00) >> 8) as usize];
+                self.write_memory(self.index as usize, value / 100)?;
+                self.write_memory(self.index as usize + 1, (value % 100) / 10)?;
+                self.write_memory(self.index as usize + 2, value % 10)?;
+                self.advance_pc(2);
             }
             
             0x0055 => {
                 //FX55: store V[0] to V[X] in memory starting with I
-                for x in 0..((self.opcode & 0x0F00) >> 8) {
-                    self.memory[self.index as usize] = self.register[x as usize];
-                    self.index += 1;
+                let last = ((self.opcode & 0x0F00) >> 8) as usize;
+                for x in 0..=last {
+                    self.write_memory(self.index as usize + x, self.register[x])?;
                 }
-                self.pc += 2;
+                self.advance_index(last);
+                self.advance_pc(2);
             }
             
             0x0065 => {
                 //FX65: store memory starting with I in V[0] to V[X]
-                for x in 0..((self.opcode & 0x0F00) >> 8) {
-                    self.register[x as usize] = self.memory[self.index as usize];
-                    self.index += 1;
+                let last = ((self.opcode & 0x0F00) >> 8) as usize;
+                for x in 0..=last {
+                    self.register[x] = self.read_memory(self.index as usize + x)?;
                 }
-                self.pc += 2;
+                self.advance_index(last);
+                self.advance_pc(2);
             }
-                   
-            _ => {
-                println!("opcode: {:X}, not implemented yet", self.opcode);
-                self.pc += 2;
+
+            0x0075 if self.mode != Mode::Chip8 => {
+                //FX75: store V[0] to V[X] in the RPL user flags
+                let last = ((self.opcode & 0x0F00) >> 8) as usize;
+                self.rpl[..=last].copy_from_slice(&self.register[..=last]);
+                self.advance_pc(2);
+            }
+
+            0x0085 if self.mode != Mode::Chip8 => {
+                //FX85: load V[0] to V[X] from the RPL user flags
+                let last = ((self.opcode & 0x0F00) >> 8) as usize;
+                self.register[..=last].copy_from_slice(&self.rpl[..=last]);
+                self.advance_pc(2);
             }
+                   
+            _ => return Err(self.invalid_opcode()),
+        }
+        Ok(())
+    }
+
+    fn advance_index(&mut self, last: usize) {
+        //FX55/FX65 side effect on I, see the load/store quirk
+        match self.quirks.load_store {
+            LoadStore::IncrementXPlusOne => self.index = self.index.wrapping_add(last as u16 + 1),
+            LoadStore::IncrementX => self.index = self.index.wrapping_add(last as u16),
+            LoadStore::Unchanged => {}
         }
     }
 }
+
+//memory of a 0NNN routine, its writes go through `write_memory` for the watchpoints
+struct VipBus<'a>(&'a mut Chip8);
+
+impl<'a> Bus for VipBus<'a> {
+    fn read(&self, address: u16) -> u8 {
+        self.0.memory[address as usize % self.0.memory.len()]
+    }
+
+    fn write(&mut self, address: u16, byte: u8) {
+        let address = address as usize % self.0.memory.len();
+        //cannot fail, the address is inside the memory
+        let _ = self.0.write_memory(address, byte);
+    }
+}
+
+//registers from x to y, counting down if x > y
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use breakpoint::{Breakpoint, Watch};
+
+    //runs one instruction per opcode of `opcodes`
+    fn run(mode: Mode, opcodes: &[u16]) -> Chip8 {
+        let rom = opcodes.iter().flat_map(|opcode| opcode.to_be_bytes().to_vec()).collect();
+        let mut chip8 = Chip8::with_mode(rom, mode).unwrap();
+        chip8.error_mode = ErrorMode::Strict;
+        for _ in opcodes {
+            chip8.run_cycle(&Keypad::new()).unwrap();
+        }
+        chip8
+    }
+
+    fn vx_vf(opcodes: &[u16]) -> (u8, u8) {
+        let chip8 = run(Mode::Chip8, opcodes);
+        (chip8.registers()[0], chip8.registers()[15])
+    }
+
+    #[test]
+    fn add_sets_carry() {
+        assert_eq!(vx_vf(&[0x60F0, 0x6120, 0x8014]), (0x10, 1));
+        assert_eq!(vx_vf(&[0x6010, 0x6120, 0x8014]), (0x30, 0));
+    }
+
+    #[test]
+    fn subtract_wraps_and_sets_no_borrow() {
+        assert_eq!(vx_vf(&[0x6005, 0x6107, 0x8015]), (0xFE, 0));
+        assert_eq!(vx_vf(&[0x6007, 0x6105, 0x8015]), (0x02, 1));
+        assert_eq!(vx_vf(&[0x6005, 0x6105, 0x8015]), (0x00, 1));
+    }
+
+    #[test]
+    fn reverse_subtract_wraps_and_sets_no_borrow() {
+        assert_eq!(vx_vf(&[0x6005, 0x6107, 0x8017]), (0x02, 1));
+        assert_eq!(vx_vf(&[0x6007, 0x6105, 0x8017]), (0xFE, 0));
+    }
+
+    #[test]
+    fn shifts_set_the_shifted_out_bit() {
+        assert_eq!(vx_vf(&[0x6181, 0x8016]), (0x40, 1));
+        assert_eq!(vx_vf(&[0x6181, 0x801E]), (0x02, 1));
+        assert_eq!(vx_vf(&[0x6141, 0x801E]), (0x82, 0));
+    }
+
+    #[test]
+    fn flag_wins_over_the_result_in_vf() {
+        //like on the VIP, V[F] ends up with the flag when it is also the target
+        assert_eq!(run(Mode::Chip8, &[0x6FF0, 0x6120, 0x8F14]).registers()[15], 1);
+        assert_eq!(run(Mode::Chip8, &[0x6F05, 0x6103, 0x8F15]).registers()[15], 1);
+        assert_eq!(run(Mode::Chip8, &[0x6F05, 0x6103, 0x8F17]).registers()[15], 0);
+        assert_eq!(run(Mode::Chip8, &[0x6F01, 0x8FF6]).registers()[15], 1);
+    }
+
+    #[test]
+    fn fetch_wraps_at_the_end_of_memory() {
+        let mut chip8 = Chip8::with_mode(Vec::new(), Mode::XoChip).unwrap();
+        chip8.error_mode = ErrorMode::Strict;
+        //the opcode 60F0 is split between the last byte and the font at 0
+        chip8.memory[0xFFFF] = 0x60;
+        chip8.pc = 0xFFFF;
+        chip8.run_cycle(&Keypad::new()).unwrap();
+        assert_eq!((chip8.registers()[0], chip8.pc()), (0xF0, 0x0001));
+
+        //the address of F000 NNNN is the font at 0
+        chip8.memory[0xFFFE] = 0xF0;
+        chip8.memory[0xFFFF] = 0x00;
+        chip8.pc = 0xFFFE;
+        chip8.run_cycle(&Keypad::new()).unwrap();
+        assert_eq!((chip8.index(), chip8.pc()), (0xF090, 0x0002));
+    }
+
+    //0200: call the routine at 0300, 0202: loop
+    //0300: R6 = 0EF0 (V0), LDI 42, STR R6, SEP R4
+    fn machine_code() -> Chip8 {
+        let mut rom = vec![0x03, 0x00, 0x12, 0x02];
+        rom.resize(0x100, 0);
+        rom.extend_from_slice(&[0xF8, 0x0E, 0xB6, 0xF8, 0xF0, 0xA6, 0xF8, 0x42, 0x56, 0xD4]);
+        let mut chip8 = Chip8::with_mode(rom, Mode::Chip8).unwrap();
+        chip8.error_mode = ErrorMode::Strict;
+        chip8
+    }
+
+    #[test]
+    fn machine_code_returns_to_the_interpreter() {
+        let mut chip8 = machine_code();
+        chip8.run_cycle(&Keypad::new()).unwrap();
+        assert_eq!((chip8.registers()[0], chip8.pc()), (0x42, 0x202));
+    }
+
+    #[test]
+    fn machine_code_writes_hit_watchpoints() {
+        let mut chip8 = machine_code();
+        let id = chip8.breakpoints.add(Breakpoint::new(Watch::Write(VIP_REGISTERS..VIP_REGISTERS + 1)));
+        chip8.run_cycle(&Keypad::new()).unwrap();
+        assert_eq!(chip8.last_hit().map(|hit| hit.id), Some(id));
+    }
+
+    #[test]
+    fn clipped_rows_only_collide_on_super_chip() {
+        //a 5 row digit drawn at the last row of the hi-res screen
+        let draw: [u16; 5] = [0x00FF, 0xA000, 0x6000, 0x613F, 0xD015];
+        let clipped = |mode: Mode| {
+            let rom = draw.iter().flat_map(|opcode| opcode.to_be_bytes().to_vec()).collect();
+            let mut chip8 = Chip8::with_mode(rom, mode).unwrap();
+            chip8.quirks = Quirks::schip();
+            for _ in draw.iter() {
+                chip8.run_cycle(&Keypad::new()).unwrap();
+            }
+            chip8.registers()[15]
+        };
+        assert_eq!(clipped(Mode::SuperChip), 4);
+        assert_eq!(clipped(Mode::XoChip), 0);
+    }
+}
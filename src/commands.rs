@@ -0,0 +1,269 @@
+use headless::{EXIT_ERROR, EXIT_OK, EXIT_USAGE};
+use read_file;
+
+use rip8::{assemble, disassemble, read_trace, Mode, Step};
+
+use std::fs::File;
+use std::io::prelude::*;
+
+pub const DISASM_USAGE: &str = "\
+Usage: rip8 disasm [--mode <chip8|schip|xochip>] [-o <FILE>] <ROM>
+
+Prints an Octo listing of the ROM, or writes it to FILE.";
+
+pub const ASM_USAGE: &str = "\
+Usage: rip8 asm <SOURCE> [-o <FILE>] [--symbols <FILE>]
+
+Assembles an Octo program into FILE (default: SOURCE with .ch8) and writes
+the label addresses and source lines to the symbol map (default: FILE with .sym).";
+
+pub const TRACE_DIFF_USAGE: &str = "\
+Usage: rip8 trace-diff [--no-screen] <TRACE> <OTHER>
+
+Compares two traces written with --trace, step by step, and reports the first
+step where pc, the registers, I or the screen differ. Exits with 0 if the
+traces match and with 1 otherwise.
+
+Text traces of other emulators need one line per instruction with the cycle,
+pc and opcode followed by any of 'V <V0 to VF as 32 hex digits>', 'I <hex>'
+and 'FB <hex>', the 64 bit FNV-1a hash of the screen with one byte per pixel.
+Fields missing from either trace are not compared. --no-screen skips the
+screen even if both traces have it.";
+
+/// `rip8 asm`, returns the exit code.
+pub fn asm(args: &[String]) -> i32 {
+    let mut source = None;
+    let mut output = None;
+    let mut symbols = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", ASM_USAGE);
+                return EXIT_OK;
+            }
+            "-o" => match args.next() {
+                Some(path) => output = Some(path.clone()),
+                None => return usage_error("missing value for -o", ASM_USAGE),
+            },
+            "--symbols" => match args.next() {
+                Some(path) => symbols = Some(path.clone()),
+                None => return usage_error("missing value for --symbols", ASM_USAGE),
+            },
+            _ if arg.starts_with('-') => return usage_error(&format!("unknown option {}", arg), ASM_USAGE),
+            _ => source = Some(arg.clone()),
+        }
+    }
+    let source = match source {
+        Some(source) => source,
+        None => return usage_error("no source file given", ASM_USAGE),
+    };
+    let output = output.unwrap_or_else(|| with_extension(&source, "ch8"));
+    let symbols = symbols.unwrap_or_else(|| with_extension(&output, "sym"));
+
+    let text = match read_file(&source).map(|data| String::from_utf8_lossy(&data).into_owned()) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Error while reading {}: {}", source, e);
+            return EXIT_USAGE;
+        }
+    };
+    let program = match assemble(&text) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("{}:{}", source, e);
+            return EXIT_ERROR;
+        }
+    };
+    let files = [(&output, program.rom.clone()), (&symbols, program.symbol_map().into_bytes())];
+    for (path, data) in files.iter() {
+        if let Err(e) = File::create(path).and_then(|mut f| f.write_all(data)) {
+            eprintln!("Error while writing {}: {}", path, e);
+            return EXIT_USAGE;
+        }
+    }
+    println!("{} bytes, {} labels", program.rom.len(), program.symbols.len());
+    EXIT_OK
+}
+
+/// Replaces the extension of `path`, or appends one.
+pub fn with_extension(path: &str, extension: &str) -> String {
+    let stem = match path.rfind('.') {
+        Some(dot) if !path[dot..].contains('/') => &path[..dot],
+        _ => path,
+    };
+    format!("{}.{}", stem, extension)
+}
+
+/// `rip8 disasm`, returns the exit code.
+pub fn disasm(args: &[String]) -> i32 {
+    let mut mode = Mode::Chip8;
+    let mut output = None;
+    let mut rom = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", DISASM_USAGE);
+                return EXIT_OK;
+            }
+            "--mode" => match args.next().map(|value| value.parse()) {
+                Some(Ok(value)) => mode = value,
+                _ => return usage_error("missing or invalid value for --mode", DISASM_USAGE),
+            },
+            "-o" => match args.next() {
+                Some(path) => output = Some(path.clone()),
+                None => return usage_error("missing value for -o", DISASM_USAGE),
+            },
+            _ if arg.starts_with('-') => return usage_error(&format!("unknown option {}", arg), DISASM_USAGE),
+            _ => rom = Some(arg.clone()),
+        }
+    }
+    let rom = match rom {
+        Some(rom) => rom,
+        None => return usage_error("no ROM given", DISASM_USAGE),
+    };
+    let data = match read_file(&rom) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Error while reading {}: {}", rom, e);
+            return EXIT_USAGE;
+        }
+    };
+    if data.len() > mode.memory_size() - 0x200 {
+        eprintln!("{} does not fit into memory in {} mode", rom, mode);
+        return EXIT_ERROR;
+    }
+
+    let listing = format!("# {} disassembled by rip8 ({} mode)\n\n{}", rom, mode, disassemble(&data, mode));
+    match output {
+        Some(path) => {
+            if let Err(e) = File::create(&path).and_then(|mut f| f.write_all(listing.as_bytes())) {
+                eprintln!("Error while writing {}: {}", path, e);
+                return EXIT_USAGE;
+            }
+        }
+        None => print!("{}", listing),
+    }
+    EXIT_OK
+}
+
+/// `rip8 trace-diff`, returns the exit code.
+pub fn trace_diff(args: &[String]) -> i32 {
+    let mut screen = true;
+    let mut paths = Vec::new();
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", TRACE_DIFF_USAGE);
+                return EXIT_OK;
+            }
+            "--no-screen" => screen = false,
+            _ if arg.starts_with('-') => return usage_error(&format!("unknown option {}", arg), TRACE_DIFF_USAGE),
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.len() != 2 {
+        return usage_error("two traces are needed", TRACE_DIFF_USAGE);
+    }
+    let mut traces = Vec::new();
+    for path in paths.iter() {
+        match read_file(path).map_err(|e| e.to_string()).and_then(|data| read_trace(&data)) {
+            Ok(steps) => traces.push(steps),
+            Err(e) => {
+                eprintln!("Error while reading {}: {}", path, e);
+                return EXIT_USAGE;
+            }
+        }
+    }
+
+    let (first, second) = (&traces[0], &traces[1]);
+    for (n, (a, b)) in first.iter().zip(second.iter()).enumerate() {
+        let differences = differences(a, b, screen);
+        if differences.is_empty() {
+            continue;
+        }
+        println!("traces diverge at step {} (cycle {} and {}):", n + 1, a.cycle, b.cycle);
+        for difference in differences {
+            println!("  {}", difference);
+        }
+        //both ran the same instruction before, its result differs
+        if n > 0 {
+            println!("after {:04X} {}", first[n - 1].pc, first[n - 1].instruction());
+        }
+        println!("{}: {}", paths[0], a);
+        println!("{}: {}", paths[1], b);
+        return EXIT_ERROR;
+    }
+    if first.len() != second.len() {
+        let (shorter, longer) = if first.len() < second.len() { (0, 1) } else { (1, 0) };
+        println!("{} ends after {} steps, {} goes on with", paths[shorter], traces[shorter].len(), paths[longer]);
+        println!("{}", traces[longer][traces[shorter].len()]);
+        return EXIT_ERROR;
+    }
+    println!("traces match for {} steps", first.len());
+    EXIT_OK
+}
+
+//the compared values of two steps that differ, fields missing from either trace are skipped
+fn differences(a: &Step, b: &Step, screen: bool) -> Vec<String> {
+    let mut differences = Vec::new();
+    if a.pc != b.pc {
+        differences.push(format!("pc {:04X} != {:04X}", a.pc, b.pc));
+    }
+    if let (Some(ra), Some(rb)) = (a.registers, b.registers) {
+        for (x, (va, vb)) in ra.iter().zip(rb.iter()).enumerate() {
+            if va != vb {
+                differences.push(format!("V{:X} {:02X} != {:02X}", x, va, vb));
+            }
+        }
+    }
+    if let (Some(ia), Some(ib)) = (a.index, b.index) {
+        if ia != ib {
+            differences.push(format!("I {:04X} != {:04X}", ia, ib));
+        }
+    }
+    if let (true, Some(sa), Some(sb)) = (screen, a.screen, b.screen) {
+        if sa != sb {
+            differences.push(format!("screen {:016X} != {:016X}", sa, sb));
+        }
+    }
+    differences
+}
+
+fn usage_error(message: &str, usage: &str) -> i32 {
+    eprintln!("{}\n\n{}", message, usage);
+    EXIT_USAGE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL: &str = "       4 0208 7102 V 01000000000000000000000000000000 I 0000 SP 1 DT 00 ST 00 FB 28C31CF8DF2EC325  v1 += 0x02";
+
+    #[test]
+    fn missing_fields_are_not_compared() {
+        let full: Step = FULL.parse().unwrap();
+        let minimal: Step = "4 0208 7102".parse().unwrap();
+        assert_eq!(minimal.registers, None);
+        assert!(differences(&full, &minimal, true).is_empty());
+        assert!(differences(&minimal, &full, true).is_empty());
+    }
+
+    #[test]
+    fn fields_in_both_traces_are_compared() {
+        let full: Step = FULL.parse().unwrap();
+        let other: Step = "4 0208 7102 V 02000000000000000000000000000000 I 0300".parse().unwrap();
+        assert_eq!(differences(&full, &other, true), vec!["V0 01 != 02", "I 0000 != 0300"]);
+        let moved: Step = "5 020A 7102".parse().unwrap();
+        assert_eq!(differences(&full, &moved, true), vec!["pc 0208 != 020A"]);
+    }
+
+    #[test]
+    fn full_step_round_trips() {
+        let full: Step = FULL.parse().unwrap();
+        assert_eq!(full.to_string(), FULL);
+        assert_eq!("4 0208 7102".parse::<Step>().unwrap().to_string(), "       4 0208 7102  v1 += 0x02");
+    }
+}
@@ -0,0 +1,908 @@
+use commands::with_extension;
+use headless::{EXIT_OK, EXIT_USAGE};
+use json::Json;
+use options::{Options, OptionsError};
+use {load_state_file, read_file, time_seed};
+
+use rip8::{Breakpoint, Chip8, Condition, Event, Hit, Instruction, Keypad, Register, Watch, Xorshift};
+
+use std::io::{self, prelude::*, BufReader};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const DAP_USAGE: &str = "\
+Usage: rip8 dap [--port <PORT>] [OPTIONS] [<ROM>]
+
+Serves the Debug Adapter Protocol on stdin/stdout, or to one client on
+127.0.0.1:PORT. Without a ROM the client starts one with a 'launch' request
+({\"program\": \"game.ch8\", \"args\": [\"--mode\", \"xochip\"]}), with a ROM
+it attaches to the already loaded machine. 'symbols' (default: the ROM with
+.sym) and 'source' (default: the ROM with .8o) map addresses to source lines.";
+
+/// Thread shown to the client, the interpreter has just one.
+const THREAD_ID: i64 = 1;
+/// `variablesReference`s of the scopes.
+const REGISTERS: i64 = 1;
+const STACK: i64 = 2;
+const TIMERS: i64 = 3;
+/// Longest message body read, longer ones are skipped.
+const MAX_MESSAGE: u64 = 1 << 24;
+
+/// Length of a 60 Hz frame while running.
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// `rip8 dap`, returns the exit code.
+pub fn run(args: &[String]) -> i32 {
+    let mut port = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", DAP_USAGE);
+                return EXIT_OK;
+            }
+            "--port" => match args.next().and_then(|value| value.parse::<u16>().ok()) {
+                Some(value) => port = Some(value),
+                None => {
+                    eprintln!("missing or invalid value for --port\n\n{}", DAP_USAGE);
+                    return EXIT_USAGE;
+                }
+            },
+            _ => rest.push(arg.clone()),
+        }
+    }
+    let mut session = Session::new();
+    if !rest.is_empty() {
+        let loaded = Options::parse(rest.into_iter())
+            .map_err(|e| match e {
+                OptionsError::Help => String::from(DAP_USAGE),
+                e => e.to_string(),
+            })
+            .and_then(|options| Machine::load(&options, &Json::Null));
+        match loaded {
+            Ok(machine) => session.attachable = Some(machine),
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_USAGE;
+            }
+        }
+    }
+
+    let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write>) = match port {
+        Some(port) => {
+            let stream = TcpListener::bind(("127.0.0.1", port))
+                .and_then(|listener| {
+                    eprintln!("waiting for a debugger on 127.0.0.1:{}", port);
+                    listener.accept()
+                })
+                .and_then(|(stream, _)| Ok((stream.try_clone()?, stream)));
+            match stream {
+                Ok((reader, writer)) => (Box::new(reader), Box::new(writer)),
+                Err(e) => {
+                    eprintln!("Error while listening on port {}: {}", port, e);
+                    return EXIT_USAGE;
+                }
+            }
+        }
+        None => (Box::new(io::stdin()), Box::new(io::stdout())),
+    };
+
+    //requests are read on their own thread so that `pause` reaches a running program
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        loop {
+            match read_message(&mut reader) {
+                Ok(Some(Ok(message))) => {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+                Ok(Some(Err(e))) => eprintln!("ignoring an invalid message: {}", e),
+                Ok(None) | Err(_) => break,
+            }
+        }
+    });
+    session.writer = Some(writer);
+    session.serve(receiver);
+    EXIT_OK
+}
+
+/// Reads one `Content-Length` framed message, `None` at the end of the input.
+///
+/// A message that is too long or not JSON is skipped and returned as an error.
+fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Result<Json, String>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() && length.is_some() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<u64>().ok();
+        }
+    }
+    let length = length.unwrap_or(0);
+    if length > MAX_MESSAGE {
+        io::copy(&mut reader.take(length), &mut io::sink())?;
+        return Ok(Some(Err(format!("{} bytes is longer than {} bytes", length, MAX_MESSAGE))));
+    }
+    let mut body = vec![0; length as usize];
+    reader.read_exact(&mut body)?;
+    Ok(Some(Json::parse(&String::from_utf8_lossy(&body))))
+}
+
+/// Label and line information of an assembled program, read from its symbol map.
+#[derive(Default)]
+struct Symbols {
+    //sorted by address
+    labels: Vec<(u16, String)>,
+    lines: Vec<(u16, usize)>,
+}
+
+impl Symbols {
+    /// Parses the `label NAME ADDR` and `line N ADDR` lines written by `rip8 asm`.
+    fn parse(map: &str) -> Symbols {
+        let mut symbols = Symbols::default();
+        for line in map.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match (words.first(), words.get(1), words.get(2).and_then(|word| u16::from_str_radix(word, 16).ok())) {
+                (Some(&"label"), Some(name), Some(address)) => symbols.labels.push((address, name.to_string())),
+                (Some(&"line"), Some(number), Some(address)) => {
+                    if let Ok(number) = number.parse() {
+                        symbols.lines.push((address, number));
+                    }
+                }
+                _ => {}
+            }
+        }
+        symbols.labels.sort();
+        symbols.lines.sort();
+        symbols
+    }
+
+    /// Names `address` after the closest label before it, e.g. `main+0x4`.
+    fn name(&self, address: u16) -> String {
+        match self.labels.iter().rev().find(|&&(label, _)| label <= address) {
+            Some(&(label, ref name)) if label == address => name.clone(),
+            Some(&(label, ref name)) => format!("{}+0x{:X}", name, address - label),
+            None => format!("0x{:03X}", address),
+        }
+    }
+
+    fn line(&self, address: u16) -> Option<usize> {
+        self.lines.iter().find(|&&(line_address, _)| line_address == address).map(|&(_, line)| line)
+    }
+
+    /// Address of the first instruction on `line` or the next line with code, and that line.
+    fn address(&self, line: usize) -> Option<(u16, usize)> {
+        self.lines.iter()
+            .filter(|&&(_, number)| number >= line)
+            .min_by_key(|&&(address, number)| (number, address))
+            .cloned()
+    }
+
+    fn label(&self, name: &str) -> Option<u16> {
+        self.labels.iter().find(|&(_, label)| label == name).map(|&(address, _)| address)
+    }
+}
+
+/// The debugged program.
+struct Machine {
+    chip8: Chip8,
+    keypad: Keypad,
+    cycles_per_frame: u64,
+    cycles: u64,
+    symbols: Symbols,
+    source: Option<String>,
+}
+
+impl Machine {
+    /// Loads the ROM of `options`, `arguments` may name the `symbols` and `source` files.
+    fn load(options: &Options, arguments: &Json) -> Result<Machine, String> {
+        let rom = read_file(&options.rom).map_err(|e| format!("Error while reading {}: {}", options.rom, e))?;
+        let mut chip8 = Chip8::with_mode(rom, options.mode).map_err(|e| e.to_string())?;
+        chip8.error_mode = options.error_mode;
+        chip8.quirks = options.quirks;
+        chip8.set_rng(Box::new(Xorshift::new(options.seed.unwrap_or_else(time_seed))));
+        let mut keypad = Keypad::new();
+        if let Some(ref path) = options.load_state {
+            keypad = load_state_file(&mut chip8, path).map_err(|e| format!("Error while loading {}: {}", path, e))?;
+        }
+
+        //the symbol map and source are optional unless named explicitly
+        let symbols = match arguments.get("symbols").as_str() {
+            Some(path) => read_file(path).map_err(|e| format!("Error while reading {}: {}", path, e))?,
+            None => read_file(&with_extension(&options.rom, "sym")).unwrap_or_default(),
+        };
+        let source = match arguments.get("source").as_str() {
+            Some(path) => Some(path.to_string()),
+            None => Some(with_extension(&options.rom, "8o")).filter(|path| read_file(path).is_ok()),
+        };
+        Ok(Machine {
+            chip8,
+            keypad,
+            cycles_per_frame: options.cycles_per_frame.max(1) as u64,
+            cycles: 0,
+            symbols: Symbols::parse(&String::from_utf8_lossy(&symbols)),
+            source,
+        })
+    }
+
+    /// Runs one instruction, ticking the timers every frame.
+    fn cycle(&mut self) -> Option<Stopped> {
+        let result = self.chip8.run_cycle(&self.keypad);
+        self.cycles += 1;
+        if self.cycles.is_multiple_of(self.cycles_per_frame) {
+            self.chip8.tick_timers();
+        }
+        if let Err(e) = result {
+            return Some(Stopped::Error(e.to_string()));
+        }
+        if self.chip8.has_exited() {
+            return Some(Stopped::Exited);
+        }
+        self.chip8.last_hit().map(|&hit| Stopped::Hit(hit))
+    }
+
+    fn source(&self) -> Json {
+        match self.source {
+            Some(ref path) => {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                Json::object(vec![("name", name.into()), ("path", path.as_str().into())])
+            }
+            None => Json::Null,
+        }
+    }
+}
+
+/// Why a running program stopped.
+enum Stopped {
+    Hit(Hit),
+    Error(String),
+    Exited,
+}
+
+/// Breakpoints of the client, by the request that set them.
+#[derive(Default)]
+struct ClientBreakpoints {
+    source: Vec<usize>,
+    instruction: Vec<usize>,
+    function: Vec<usize>,
+}
+
+/// A debug session with one client.
+struct Session {
+    writer: Option<Box<dyn Write>>,
+    seq: i64,
+    machine: Option<Machine>,
+    //machine loaded from the command line, taken by `attach`
+    attachable: Option<Machine>,
+    breakpoints: ClientBreakpoints,
+    stop_on_entry: bool,
+    running: bool,
+    //temporary breakpoint of `next` and `stepOut`
+    step: Option<usize>,
+    //set by `next` and `stepIn` for a single instruction
+    step_once: bool,
+}
+
+impl Session {
+    fn new() -> Session {
+        Session {
+            writer: None,
+            seq: 0,
+            machine: None,
+            attachable: None,
+            breakpoints: ClientBreakpoints::default(),
+            stop_on_entry: false,
+            running: false,
+            step: None,
+            step_once: false,
+        }
+    }
+
+    /// Handles requests until the client disconnects, running the program in real time in between.
+    fn serve(&mut self, receiver: Receiver<Json>) {
+        let mut next_frame = Instant::now();
+        loop {
+            let message = if self.running {
+                match receiver.recv_timeout(next_frame.saturating_duration_since(Instant::now())) {
+                    Ok(message) => Some(message),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            } else {
+                match receiver.recv() {
+                    Ok(message) => Some(message),
+                    Err(_) => return,
+                }
+            };
+            match message {
+                Some(message) => {
+                    if !self.handle(&message) {
+                        return;
+                    }
+                    next_frame = Instant::now();
+                }
+                None => {
+                    self.run_frame();
+                    next_frame = (next_frame + FRAME).max(Instant::now());
+                }
+            }
+        }
+    }
+
+    fn run_frame(&mut self) {
+        let stopped = match self.machine {
+            Some(ref mut machine) => (0..machine.cycles_per_frame).filter_map(|_| machine.cycle()).next(),
+            None => return,
+        };
+        if let Some(stopped) = stopped {
+            self.stop(stopped);
+        }
+    }
+
+    /// Reports why the program stopped.
+    fn stop(&mut self, stopped: Stopped) {
+        self.running = false;
+        if let Some(id) = self.step.take() {
+            if let Some(ref mut machine) = self.machine {
+                machine.chip8.breakpoints.remove(id);
+            }
+            if let Stopped::Hit(hit) = stopped {
+                if hit.id == id {
+                    return self.stopped("step", None, Vec::new());
+                }
+            }
+        }
+        match stopped {
+            Stopped::Hit(hit) => {
+                let reason = match hit.event {
+                    Event::Execute(_) => "breakpoint",
+                    Event::Read(_) | Event::Write(_) | Event::Register { .. } | Event::Condition => "data breakpoint",
+                };
+                self.stopped(reason, Some(hit.to_string()), vec![(hit.id as i64).into()]);
+            }
+            Stopped::Error(e) => self.stopped("exception", Some(e), Vec::new()),
+            Stopped::Exited => {
+                self.event("exited", Json::object(vec![("exitCode", 0.into())]));
+                self.event("terminated", Json::object(Vec::new()));
+            }
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, description: Option<String>, hit_breakpoint_ids: Vec<Json>) {
+        let mut body = vec![
+            ("reason", reason.into()),
+            ("threadId", THREAD_ID.into()),
+            ("allThreadsStopped", true.into()),
+        ];
+        if let Some(description) = description {
+            body.push(("description", description.clone().into()));
+            body.push(("text", description.into()));
+        }
+        if !hit_breakpoint_ids.is_empty() {
+            body.push(("hitBreakpointIds", hit_breakpoint_ids.into()));
+        }
+        self.event("stopped", Json::object(body));
+    }
+
+    /// Answers one request, returns false once the session is over.
+    fn handle(&mut self, message: &Json) -> bool {
+        if message.get("type").as_str() != Some("request") {
+            return true;
+        }
+        let command = message.get("command").as_str().unwrap_or("");
+        let arguments = message.get("arguments");
+        let result = self.request(command, arguments);
+        let mut response = vec![
+            ("type", "response".into()),
+            ("request_seq", message.get("seq").clone()),
+            ("command", command.into()),
+            ("success", result.is_ok().into()),
+        ];
+        match result {
+            Ok(body) => response.push(("body", body)),
+            Err(e) => response.push(("message", e.into())),
+        }
+        self.send(Json::object(response));
+
+        match command {
+            "initialize" => {}
+            "launch" | "attach" if self.machine.is_some() => self.event("initialized", Json::object(Vec::new())),
+            "configurationDone" if self.stop_on_entry => self.stopped("entry", None, Vec::new()),
+            "configurationDone" => self.running = true,
+            "disconnect" | "terminate" => return false,
+            //a single instruction runs right away
+            _ if self.step_once => {
+                self.step_once = false;
+                let stopped = self.machine.as_mut().and_then(|machine| machine.cycle());
+                match stopped {
+                    Some(Stopped::Hit(_)) | None => self.stopped("step", None, Vec::new()),
+                    Some(stopped) => self.stop(stopped),
+                }
+            }
+            "pause" if self.running => {
+                self.running = false;
+                if let (Some(id), Some(machine)) = (self.step.take(), self.machine.as_mut()) {
+                    machine.chip8.breakpoints.remove(id);
+                }
+                self.stopped("pause", None, Vec::new());
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn request(&mut self, command: &str, arguments: &Json) -> Result<Json, String> {
+        match command {
+            "initialize" => return Ok(Json::object(vec![
+                ("supportsConfigurationDoneRequest", true.into()),
+                ("supportsConditionalBreakpoints", true.into()),
+                ("supportsFunctionBreakpoints", true.into()),
+                ("supportsInstructionBreakpoints", true.into()),
+                ("supportsReadMemoryRequest", true.into()),
+                ("supportsTerminateRequest", true.into()),
+                ("supportsEvaluateForHovers", true.into()),
+            ])),
+            "launch" => {
+                let program = arguments.get("program").as_str().ok_or("'program' is missing")?;
+                let mut args: Vec<String> = arguments.get("args").as_array().iter()
+                    .filter_map(|arg| arg.as_str().map(|arg| arg.to_string()))
+                    .collect();
+                args.push(program.to_string());
+                let options = Options::parse(args.into_iter()).map_err(|e| e.to_string())?;
+                self.machine = Some(Machine::load(&options, arguments)?);
+                self.stop_on_entry = arguments.get("stopOnEntry").as_bool().unwrap_or(false);
+                return Ok(Json::Null);
+            }
+            "attach" => {
+                let machine = self.attachable.take()
+                    .ok_or("no ROM loaded, start the server with 'rip8 dap --port <PORT> <ROM>'")?;
+                self.machine = Some(machine);
+                self.stop_on_entry = arguments.get("stopOnEntry").as_bool().unwrap_or(false);
+                return Ok(Json::Null);
+            }
+            "disconnect" | "terminate" | "configurationDone" => return Ok(Json::Null),
+            "threads" => return Ok(Json::object(vec![
+                ("threads", vec![Json::object(vec![("id", THREAD_ID.into()), ("name", "CHIP-8".into())])].into()),
+            ])),
+            _ => {}
+        }
+
+        let machine = self.machine.as_mut().ok_or("no program has been launched")?;
+        match command {
+            "setBreakpoints" => {
+                let requested = arguments.get("breakpoints").as_array();
+                let mut breakpoints = Vec::new();
+                for breakpoint in requested {
+                    let line = breakpoint.get("line").as_i64().unwrap_or(0).max(0) as usize;
+                    breakpoints.push(match machine.symbols.address(line) {
+                        Some((address, line)) => (Some(address), Some(line), breakpoint.get("condition").as_str()),
+                        None => (None, None, None),
+                    });
+                }
+                let ids = &mut self.breakpoints.source;
+                let results = set_breakpoints(&mut machine.chip8, ids, breakpoints.into_iter().map(|(address, line, condition)| {
+                    let result = match address {
+                        Some(address) => Ok(address),
+                        None => Err(String::from("no code on this line or later")),
+                    };
+                    (result, condition, line.map(|line| ("line", (line as i64).into())))
+                }));
+                Ok(Json::object(vec![("breakpoints", results.into())]))
+            }
+            "setInstructionBreakpoints" => {
+                let requested = arguments.get("breakpoints").as_array();
+                let memory = machine.chip8.memory().len() as i64;
+                let mut addresses = Vec::new();
+                for breakpoint in requested {
+                    let reference = breakpoint.get("instructionReference").as_str().unwrap_or("");
+                    let offset = breakpoint.get("offset").as_i64().unwrap_or(0);
+                    addresses.push(match parse_address(reference) {
+                        Ok(address) => match (address as i64).saturating_add(offset) {
+                            address if (0..memory).contains(&address) => Ok(address as u16),
+                            _ => return Err(format!("{} with offset {} is outside the memory", reference, offset)),
+                        },
+                        Err(e) => Err(e),
+                    });
+                }
+                let ids = &mut self.breakpoints.instruction;
+                let results = set_breakpoints(&mut machine.chip8, ids, requested.iter().zip(addresses).map(|(breakpoint, result)| {
+                    let reference = result.as_ref().ok().map(|&address| ("instructionReference", format!("0x{:03X}", address).into()));
+                    (result, breakpoint.get("condition").as_str(), reference)
+                }));
+                Ok(Json::object(vec![("breakpoints", results.into())]))
+            }
+            "setFunctionBreakpoints" => {
+                let requested = arguments.get("breakpoints").as_array();
+                let ids = &mut self.breakpoints.function;
+                let symbols = &machine.symbols;
+                let memory = machine.chip8.memory().len();
+                let results = set_breakpoints(&mut machine.chip8, ids, requested.iter().map(|breakpoint| {
+                    let name = breakpoint.get("name").as_str().unwrap_or("");
+                    let result = symbols.label(name)
+                        .or_else(|| parse_address(name).ok().filter(|&address| (address as usize) < memory))
+                        .ok_or_else(|| format!("unknown label or address '{}'", name));
+                    (result, breakpoint.get("condition").as_str(), None)
+                }));
+                Ok(Json::object(vec![("breakpoints", results.into())]))
+            }
+            "stackTrace" => {
+                let chip8 = &machine.chip8;
+                //the return addresses point behind the calls
+                let addresses = Some(chip8.pc()).into_iter()
+                    .chain(chip8.stack().iter().rev().map(|&address| address.wrapping_sub(2)));
+                let frames: Vec<Json> = addresses.enumerate().map(|(id, address)| {
+                    let line = machine.symbols.line(address).filter(|_| machine.source.is_some());
+                    let mut frame = vec![
+                        ("id", (id as i64).into()),
+                        ("name", machine.symbols.name(address).into()),
+                        ("instructionPointerReference", format!("0x{:03X}", address).into()),
+                        ("line", (line.unwrap_or(0) as i64).into()),
+                        ("column", 0.into()),
+                    ];
+                    if line.is_some() {
+                        frame.push(("source", machine.source()));
+                    }
+                    Json::object(frame)
+                }).collect();
+                let total = frames.len() as i64;
+                Ok(Json::object(vec![("stackFrames", frames.into()), ("totalFrames", total.into())]))
+            }
+            "scopes" => {
+                let scope = |name: &str, reference: i64| Json::object(vec![
+                    ("name", name.into()),
+                    ("variablesReference", reference.into()),
+                    ("expensive", false.into()),
+                ]);
+                Ok(Json::object(vec![
+                    ("scopes", vec![scope("Registers", REGISTERS), scope("Stack", STACK), scope("Timers", TIMERS)].into()),
+                ]))
+            }
+            "variables" => {
+                let chip8 = &machine.chip8;
+                let variable = |name: String, value: String, memory: Option<u16>| {
+                    let mut variable = vec![("name", name.into()), ("value", value.into()), ("variablesReference", 0.into())];
+                    if let Some(address) = memory {
+                        variable.push(("memoryReference", format!("0x{:03X}", address).into()));
+                    }
+                    Json::object(variable)
+                };
+                let variables: Vec<Json> = match arguments.get("variablesReference").as_i64() {
+                    Some(REGISTERS) => {
+                        let mut variables = vec![
+                            variable(String::from("PC"), format!("0x{:03X}", chip8.pc()), Some(chip8.pc())),
+                            variable(String::from("I"), format!("0x{:03X}", chip8.index()), Some(chip8.index())),
+                        ];
+                        for (x, value) in chip8.registers().iter().enumerate() {
+                            variables.push(variable(format!("V{:X}", x), format!("0x{:02X}", value), None));
+                        }
+                        variables
+                    }
+                    Some(STACK) => chip8.stack().iter().enumerate().rev()
+                        .map(|(level, &address)| variable(format!("{}", level), format!("0x{:03X}", address), Some(address)))
+                        .collect(),
+                    Some(TIMERS) => vec![
+                        variable(String::from("delay"), chip8.delay_timer().to_string(), None),
+                        variable(String::from("sound"), chip8.sound_timer().to_string(), None),
+                        variable(String::from("cycles"), machine.cycles.to_string(), None),
+                    ],
+                    _ => Vec::new(),
+                };
+                Ok(Json::object(vec![("variables", variables.into())]))
+            }
+            "readMemory" => {
+                let start = parse_address(arguments.get("memoryReference").as_str().unwrap_or(""))? as i64;
+                let start = start.saturating_add(arguments.get("offset").as_i64().unwrap_or(0));
+                let count = arguments.get("count").as_i64().unwrap_or(0).max(0);
+                let memory = machine.chip8.memory();
+                let (from, to) = (start.max(0) as usize, start.saturating_add(count).max(0) as usize);
+                let data = memory.get(from.min(memory.len())..to.min(memory.len())).unwrap_or(&[]);
+                Ok(Json::object(vec![
+                    ("address", format!("0x{:03X}", start.max(0)).into()),
+                    ("data", base64(data).into()),
+                    ("unreadableBytes", (count - data.len() as i64).into()),
+                ]))
+            }
+            "evaluate" => {
+                let expression = arguments.get("expression").as_str().unwrap_or("").trim();
+                let words: Vec<&str> = expression.split_whitespace().collect();
+                let result = if let Ok(register) = expression.parse::<Register>() {
+                    format!("0x{:X}", register.value(&machine.chip8))
+                } else if words.len() == 3 && words[0] == "key" {
+                    //keypad input for programs waiting for a key, e.g. `key 5 down`
+                    let key = u8::from_str_radix(words[1], 16).ok().filter(|&key| key < 16).ok_or("invalid key")?;
+                    match words[2] {
+                        "down" => machine.keypad.press(key),
+                        "up" => machine.keypad.release(key),
+                        _ => return Err(String::from("usage: key <KEY> <down|up>")),
+                    }
+                    format!("key {:X} {}", key, words[2])
+                } else {
+                    let condition: Condition = expression.parse()?;
+                    condition.eval(&machine.chip8).to_string()
+                };
+                Ok(Json::object(vec![("result", result.into()), ("variablesReference", 0.into())]))
+            }
+            "continue" => {
+                self.running = true;
+                Ok(Json::object(vec![("allThreadsContinued", true.into())]))
+            }
+            "next" | "stepIn" | "stepOut" => {
+                let chip8 = &mut machine.chip8;
+                let depth = chip8.stack().len();
+                let next = Instruction::decode_at(chip8.memory(), chip8.pc() as usize);
+                let step = match (command, next) {
+                    //over a call: back at the next instruction with the same stack depth
+                    ("next", Some(Instruction::Call(_))) =>
+                        Breakpoint::new(Watch::Execute(chip8.pc_after(2))).when(format!("SP == {}", depth).parse()?),
+                    ("next", _) | ("stepIn", _) => {
+                        self.step_once = true;
+                        return Ok(Json::Null);
+                    }
+                    _ if depth == 0 => return Err(String::from("not inside a subroutine")),
+                    _ => Breakpoint::new(Watch::Condition(format!("SP < {}", depth).parse()?)),
+                };
+                self.step = Some(chip8.breakpoints.add(step));
+                self.running = true;
+                Ok(Json::Null)
+            }
+            "pause" => Ok(Json::Null),
+            _ => Err(format!("unsupported request '{}'", command)),
+        }
+    }
+
+    fn send(&mut self, mut message: Json) {
+        self.seq += 1;
+        if let Json::Object(ref mut members) = message {
+            members.insert(0, (String::from("seq"), self.seq.into()));
+        }
+        let body = message.to_string();
+        if let Some(ref mut writer) = self.writer {
+            let _ = write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body).and_then(|_| writer.flush());
+        }
+    }
+
+    fn event(&mut self, event: &str, body: Json) {
+        self.send(Json::object(vec![("type", "event".into()), ("event", event.into()), ("body", body)]));
+    }
+}
+
+/// Replaces the breakpoints in `ids` with `requested` and returns the DAP `Breakpoint`s.
+fn set_breakpoints<'a, I>(chip8: &mut Chip8, ids: &mut Vec<usize>, requested: I) -> Vec<Json>
+    where I: Iterator<Item = (Result<u16, String>, Option<&'a str>, Option<(&'static str, Json)>)> {
+    for id in ids.drain(..) {
+        chip8.breakpoints.remove(id);
+    }
+    let mut results = Vec::new();
+    for (address, condition, extra) in requested {
+        let breakpoint = address.and_then(|address| {
+            let breakpoint = Breakpoint::new(Watch::Execute(address));
+            match condition.filter(|condition| !condition.trim().is_empty()) {
+                Some(condition) => Ok(breakpoint.when(condition.parse()?)),
+                None => Ok(breakpoint),
+            }
+        });
+        let mut result = match breakpoint {
+            Ok(breakpoint) => {
+                let id = chip8.breakpoints.add(breakpoint);
+                ids.push(id);
+                vec![("id", (id as i64).into()), ("verified", true.into())]
+            }
+            Err(e) => vec![("verified", false.into()), ("message", e.into())],
+        };
+        result.extend(extra);
+        results.push(Json::object(result));
+    }
+    results
+}
+
+/// Parses `0x21A`, `21A` as hex.
+fn parse_address(reference: &str) -> Result<u16, String> {
+    let digits = reference.trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{}'", reference))
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rip8::Mode;
+    use std::cell::RefCell;
+    use std::mem;
+    use std::rc::Rc;
+
+    //V0 = 5, I = 300, calls 208 and loops at 206
+    const ROM: [u8; 10] = [0x60, 0x05, 0xA3, 0x00, 0x22, 0x08, 0x12, 0x06, 0x00, 0xEE];
+    const SYMBOLS: &str = "label main 200\nlabel sub 208\nline 3 200\nline 4 202\nline 6 204\nline 10 208\n";
+
+    //the messages sent by the session
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(data)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn session() -> (Session, Output) {
+        let machine = Machine {
+            chip8: Chip8::with_mode(ROM.to_vec(), Mode::Chip8).unwrap(),
+            keypad: Keypad::new(),
+            cycles_per_frame: 10,
+            cycles: 0,
+            symbols: Symbols::parse(SYMBOLS),
+            source: None,
+        };
+        let output = Output::default();
+        let mut session = Session::new();
+        session.writer = Some(Box::new(output.clone()));
+        session.machine = Some(machine);
+        (session, output)
+    }
+
+    //handles a request and returns the response followed by the events it caused
+    fn request(session: &mut Session, output: &Output, command: &str, arguments: &str) -> Vec<Json> {
+        let message = format!(r#"{{"seq":7,"type":"request","command":"{}","arguments":{}}}"#, command, arguments);
+        assert!(session.handle(&Json::parse(&message).unwrap()));
+        let written = mem::take(&mut *output.0.borrow_mut());
+        let mut reader = &written[..];
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut reader).unwrap() {
+            messages.push(message.unwrap());
+        }
+        let response = &messages[0];
+        assert_eq!(response.get("type").as_str(), Some("response"));
+        assert_eq!(response.get("request_seq").as_i64(), Some(7));
+        assert_eq!(response.get("command").as_str(), Some(command));
+        messages
+    }
+
+    fn breakpoints(response: &Json) -> Vec<String> {
+        response.get("body").get("breakpoints").as_array().iter().map(|breakpoint| breakpoint.to_string()).collect()
+    }
+
+    #[test]
+    fn initialize() {
+        let (mut session, output) = session();
+        let messages = request(&mut session, &output, "initialize", "{}");
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].get("seq").as_i64(), Some(1));
+        assert_eq!(messages[0].get("success").as_bool(), Some(true));
+        let body = messages[0].get("body");
+        assert_eq!(body.get("supportsReadMemoryRequest").as_bool(), Some(true));
+        assert_eq!(body.get("supportsConditionalBreakpoints").as_bool(), Some(true));
+
+        let messages = request(&mut session, &output, "frobnicate", "{}");
+        assert_eq!(messages[0].get("seq").as_i64(), Some(2));
+        assert_eq!(messages[0].get("success").as_bool(), Some(false));
+        assert_eq!(messages[0].get("message").as_str(), Some("unsupported request 'frobnicate'"));
+    }
+
+    #[test]
+    fn set_breakpoints() {
+        let (mut session, output) = session();
+        let arguments = r#"{"breakpoints":[{"line":3},{"line":5,"condition":"V0 == 5"},{"line":11},{"line":4,"condition":"V0 = 5"}]}"#;
+        let messages = request(&mut session, &output, "setBreakpoints", arguments);
+        assert_eq!(breakpoints(&messages[0]), [
+            r#"{"id":1,"verified":true,"line":3}"#,
+            r#"{"id":2,"verified":true,"line":6}"#,
+            r#"{"verified":false,"message":"no code on this line or later"}"#,
+            r#"{"verified":false,"message":"expected a comparison instead of '='","line":4}"#,
+        ]);
+        let chip8 = &session.machine.as_ref().unwrap().chip8;
+        assert_eq!(chip8.breakpoints.get(2).map(|breakpoint| breakpoint.to_string()), Some(String::from("break at 204 if V0 == 0x5")));
+
+        //the breakpoint at line 6 stops the program
+        request(&mut session, &output, "continue", "{}");
+        session.run_frame();
+        let written = mem::take(&mut *output.0.borrow_mut());
+        let stopped = read_message(&mut &written[..]).unwrap().unwrap().unwrap();
+        assert_eq!(stopped.get("event").as_str(), Some("stopped"));
+        assert_eq!(stopped.get("body").get("hitBreakpointIds").to_string(), "[2]");
+        assert_eq!(session.machine.as_ref().unwrap().chip8.pc(), 0x204);
+
+        //the next request replaces them
+        let messages = request(&mut session, &output, "setBreakpoints", r#"{"breakpoints":[]}"#);
+        assert!(breakpoints(&messages[0]).is_empty());
+        assert!(session.machine.as_ref().unwrap().chip8.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn set_instruction_breakpoints() {
+        let (mut session, output) = session();
+        let arguments = r#"{"breakpoints":[{"instructionReference":"0x200","offset":6},{"instructionReference":"nowhere"}]}"#;
+        let messages = request(&mut session, &output, "setInstructionBreakpoints", arguments);
+        assert_eq!(breakpoints(&messages[0]), [
+            r#"{"id":1,"verified":true,"instructionReference":"0x206"}"#,
+            r#"{"verified":false,"message":"invalid address 'nowhere'"}"#,
+        ]);
+        for arguments in [
+            r#"{"breakpoints":[{"instructionReference":"0xFFE","offset":2}]}"#,
+            r#"{"breakpoints":[{"instructionReference":"0x1000"}]}"#,
+            r#"{"breakpoints":[{"instructionReference":"0x0","offset":-1}]}"#,
+        ].iter() {
+            let messages = request(&mut session, &output, "setInstructionBreakpoints", arguments);
+            assert_eq!(messages[0].get("success").as_bool(), Some(false), "{}", arguments);
+        }
+        let messages = request(&mut session, &output, "setInstructionBreakpoints", r#"{"breakpoints":[{"instructionReference":"0xFFE","offset":2}]}"#);
+        assert_eq!(messages[0].get("message").as_str(), Some("0xFFE with offset 2 is outside the memory"));
+        //a failed request keeps the breakpoints
+        assert_eq!(session.machine.as_ref().unwrap().chip8.breakpoints.len(), 1);
+    }
+
+    #[test]
+    fn read_memory() {
+        let (mut session, output) = session();
+        let messages = request(&mut session, &output, "readMemory", r#"{"memoryReference":"0x200","count":4}"#);
+        assert_eq!(messages[0].get("body").to_string(), r#"{"address":"0x200","data":"YAWjAA==","unreadableBytes":0}"#);
+        let messages = request(&mut session, &output, "readMemory", r#"{"memoryReference":"0x1FF","offset":10,"count":1}"#);
+        assert_eq!(messages[0].get("body").to_string(), r#"{"address":"0x209","data":"7g==","unreadableBytes":0}"#);
+        let messages = request(&mut session, &output, "readMemory", r#"{"memoryReference":"0xFFE","count":4}"#);
+        assert_eq!(messages[0].get("body").to_string(), r#"{"address":"0xFFE","data":"AAA=","unreadableBytes":2}"#);
+        let messages = request(&mut session, &output, "readMemory", r#"{"memoryReference":"zz","count":4}"#);
+        assert_eq!(messages[0].get("message").as_str(), Some("invalid address 'zz'"));
+    }
+
+    #[test]
+    fn step_in() {
+        let (mut session, output) = session();
+        let mut pcs = Vec::new();
+        for _ in 0..4 {
+            let messages = request(&mut session, &output, "stepIn", r#"{"threadId":1}"#);
+            assert_eq!(messages.len(), 2);
+            assert_eq!(messages[0].get("success").as_bool(), Some(true));
+            assert_eq!(messages[1].get("event").as_str(), Some("stopped"));
+            assert_eq!(messages[1].get("body").get("reason").as_str(), Some("step"));
+            pcs.push(session.machine.as_ref().unwrap().chip8.pc());
+        }
+        //into the subroutine and back out
+        assert_eq!(pcs, [0x202, 0x204, 0x208, 0x206]);
+        assert_eq!(session.machine.as_ref().unwrap().chip8.registers()[0], 5);
+        assert!(!session.running);
+    }
+
+    #[test]
+    fn invalid_messages_are_skipped() {
+        let input = b"Content-Length: 5\r\n\r\n{bad}Content-Length: 99999999999\r\n\r\n[]Content-Length: 2\r\n\r\n{}";
+        let mut reader = &input[..];
+        assert!(read_message(&mut reader).unwrap().unwrap().is_err());
+        assert!(read_message(&mut reader).unwrap().unwrap().is_err());
+        assert!(read_message(&mut reader).unwrap().is_none());
+
+        let input = b"Content-Length: 5\r\n\r\n[[[[[Content-Length: 2\r\n\r\n{}";
+        let mut reader = &input[..];
+        assert!(read_message(&mut reader).unwrap().unwrap().is_err());
+        assert_eq!(read_message(&mut reader).unwrap().unwrap(), Ok(Json::Object(Vec::new())));
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+}
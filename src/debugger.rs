@@ -0,0 +1,312 @@
+use headless::{register_dump, EXIT_OK};
+
+use rip8::{Breakpoint, Chip8, Condition, Hit, Instruction, Keypad, Register, Watch};
+
+use std::io::{self, prelude::*};
+
+/// Cycles `continue` and `next` run at most before giving control back.
+const RUN_LIMIT: u64 = 1_000_000;
+/// Instructions shown by `dis` without a count.
+const DISASSEMBLY_LINES: usize = 10;
+/// Bytes shown by `mem` without a length.
+const DUMP_LENGTH: usize = 64;
+
+pub const HELP: &str = "\
+Commands (numbers and addresses are hex):
+    s, step [N]          run N instructions (default: 1)
+    n, next              step over subroutine calls
+    c, continue          run until a breakpoint, an error or 1000000 instructions
+    b, break <ADDR> [if <COND>]
+                         set a breakpoint, optionally with a condition
+    w, watch <read|write> <ADDR> [LEN]
+                         stop when memory is read or written
+    w, watch <REG>       stop when a register changes (V0-VF, I, PC, SP, DT, ST)
+    when <COND>          stop when a condition becomes true, e.g. V3 == 5 && I > 0x300
+    d, delete <ID>       remove a breakpoint or watchpoint
+    breaks               list the breakpoints and watchpoints
+    r, regs              show registers, I, pc, stack and timers
+    x, mem <ADDR> [LEN]  hex dump of memory
+    set <ADDR> <BYTE>... write bytes to memory
+    dis [ADDR] [N]       disassemble N instructions (default: around pc)
+    key <KEY> <down|up>  press or release a keypad key
+    q, quit              leave the debugger
+An empty line repeats the last command.";
+
+/// Why the machine stopped running.
+enum Stop {
+    Done,
+    Hit(Hit),
+    Error(String),
+    Exited,
+    Limit,
+}
+
+/// Interactive debugger reading commands from stdin.
+pub struct Debugger {
+    keypad: Keypad,
+    cycles_per_frame: u64,
+    cycles: u64,
+}
+
+impl Debugger {
+    pub fn new(keypad: Keypad, cycles_per_frame: u32) -> Debugger {
+        Debugger {
+            keypad,
+            cycles_per_frame: cycles_per_frame.max(1) as u64,
+            cycles: 0,
+        }
+    }
+
+    /// Runs the REPL until `quit` or the end of input, returns the exit code.
+    pub fn run(&mut self, chip8: &mut Chip8) -> i32 {
+        println!("rip8 debugger, 'help' lists the commands");
+        self.show_current(chip8);
+        let stdin = io::stdin();
+        let mut last = String::new();
+        loop {
+            print!("(rip8) ");
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => return EXIT_OK,
+                Ok(_) => {}
+            }
+            let line = match line.trim() {
+                "" => last.clone(),
+                line => line.to_string(),
+            };
+            let args: Vec<&str> = line.split_whitespace().collect();
+            if args.is_empty() {
+                continue;
+            }
+            if args[0] == "q" || args[0] == "quit" {
+                return EXIT_OK;
+            }
+            if let Err(e) = self.command(chip8, &args) {
+                println!("{}", e);
+            }
+            last = line.clone();
+        }
+    }
+
+    fn command(&mut self, chip8: &mut Chip8, args: &[&str]) -> Result<(), String> {
+        match args[0] {
+            "h" | "help" => println!("{}", HELP),
+            "s" | "step" => {
+                let count = optional_hex(args.get(1), 1)?;
+                let stop = self.run_cycles(chip8, count as u64);
+                self.report(chip8, stop);
+            }
+            "n" | "next" => {
+                let stop = match Instruction::decode_at(chip8.memory(), chip8.pc() as usize) {
+                    Some(Instruction::Call(_)) => {
+                        //a temporary breakpoint on the return from this call, not from a recursive one
+                        let depth = format!("SP == {}", chip8.stack().len()).parse()?;
+                        let id = chip8.breakpoints.add(Breakpoint::new(Watch::Execute(chip8.pc_after(2))).when(depth));
+                        let stop = self.run_cycles(chip8, RUN_LIMIT);
+                        chip8.breakpoints.remove(id);
+                        match stop {
+                            Stop::Hit(hit) if hit.id == id => Stop::Done,
+                            stop => stop,
+                        }
+                    }
+                    _ => self.run_cycles(chip8, 1),
+                };
+                self.report(chip8, stop);
+            }
+            "c" | "continue" => {
+                let stop = self.run_cycles(chip8, RUN_LIMIT);
+                self.report(chip8, stop);
+            }
+            "b" | "break" => {
+                let address = address_arg(chip8, args.get(1))?;
+                let mut breakpoint = Breakpoint::new(Watch::Execute(address as u16));
+                match args.get(2) {
+                    Some(&"if") => breakpoint = breakpoint.when(args[3..].join(" ").parse()?),
+                    Some(_) => return Err(String::from("usage: break <ADDR> [if <COND>]")),
+                    None => {}
+                }
+                self.add_breakpoint(chip8, breakpoint);
+            }
+            "w" | "watch" => {
+                let watch = match args.get(1) {
+                    Some(&"read") | Some(&"write") => {
+                        let address = address_arg(chip8, args.get(2))?;
+                        let end = address.checked_add(optional_hex(args.get(3), 1)?)
+                            .ok_or_else(|| String::from("usage: watch <read|write> <ADDR> [LEN], LEN is too large"))?;
+                        let range = address..end;
+                        if args[1] == "read" { Watch::Read(range) } else { Watch::Write(range) }
+                    }
+                    Some(register) => Watch::Register(register.parse::<Register>()?),
+                    None => return Err(String::from("usage: watch <read|write> <ADDR> [LEN] or watch <REG>")),
+                };
+                self.add_breakpoint(chip8, Breakpoint::new(watch));
+            }
+            "when" => {
+                let condition: Condition = args[1..].join(" ").parse()?;
+                self.add_breakpoint(chip8, Breakpoint::new(Watch::Condition(condition)));
+            }
+            "d" | "delete" => {
+                let id = args.get(1).and_then(|id| id.parse().ok()).ok_or_else(|| String::from("usage: delete <ID>"))?;
+                if !chip8.breakpoints.remove(id) {
+                    return Err(format!("no breakpoint {}", id));
+                }
+            }
+            "breaks" => {
+                for (id, breakpoint) in chip8.breakpoints.iter() {
+                    println!("{}: {}", id, breakpoint);
+                }
+            }
+            "r" | "regs" => {
+                print!("{}", register_dump(chip8));
+                println!("cycles: {}", self.cycles);
+            }
+            "x" | "mem" => {
+                let address = address_arg(chip8, args.get(1))?;
+                let length = optional_hex(args.get(2), DUMP_LENGTH)?;
+                print!("{}", hex_dump(chip8.memory(), address, length));
+            }
+            "set" => {
+                let address = hex_arg(args.get(1))?;
+                if args.len() < 3 {
+                    return Err(String::from("usage: set <ADDR> <BYTE>..."));
+                }
+                for (i, arg) in args[2..].iter().enumerate() {
+                    let byte = u8::from_str_radix(arg, 16).map_err(|_| format!("invalid byte '{}'", arg))?;
+                    match chip8.memory_mut().get_mut(address.saturating_add(i)) {
+                        Some(cell) => *cell = byte,
+                        None => return Err(format!("address {:X} is out of range", address.saturating_add(i))),
+                    }
+                }
+            }
+            "dis" => {
+                let pc = chip8.pc() as usize;
+                let address = match args.get(1) {
+                    Some(_) => address_arg(chip8, args.get(1))?,
+                    None => pc.saturating_sub(DISASSEMBLY_LINES / 2 * 2),
+                };
+                let count = optional_hex(args.get(2), DISASSEMBLY_LINES)?;
+                print!("{}", self.disassembly(chip8, address, count));
+            }
+            "key" => {
+                let key = hex_arg(args.get(1))?;
+                if key > 0xF {
+                    return Err(format!("invalid key {:X}", key));
+                }
+                match args.get(2) {
+                    Some(&"down") => self.keypad.press(key as u8),
+                    Some(&"up") => self.keypad.release(key as u8),
+                    _ => return Err(String::from("usage: key <KEY> <down|up>")),
+                }
+            }
+            command => return Err(format!("unknown command '{}', 'help' lists the commands", command)),
+        }
+        Ok(())
+    }
+
+    fn add_breakpoint(&self, chip8: &mut Chip8, breakpoint: Breakpoint) {
+        let id = chip8.breakpoints.add(breakpoint);
+        println!("{}: {}", id, chip8.breakpoints.get(id).unwrap());
+    }
+
+    /// Runs up to `count` instructions, ticking the timers every frame.
+    ///
+    /// Stops early at a breakpoint, an error or the end of the program.
+    fn run_cycles(&mut self, chip8: &mut Chip8, count: u64) -> Stop {
+        for _ in 0..count {
+            let result = chip8.run_cycle(&self.keypad);
+            self.cycles += 1;
+            if self.cycles.is_multiple_of(self.cycles_per_frame) {
+                chip8.tick_timers();
+            }
+            if let Err(e) = result {
+                return Stop::Error(e.to_string());
+            }
+            if chip8.has_exited() {
+                return Stop::Exited;
+            }
+            if let Some(&hit) = chip8.last_hit() {
+                return Stop::Hit(hit);
+            }
+        }
+        if count >= RUN_LIMIT { Stop::Limit } else { Stop::Done }
+    }
+
+    fn report(&self, chip8: &Chip8, stop: Stop) {
+        match stop {
+            Stop::Done => {}
+            Stop::Hit(hit) => println!("{}", hit),
+            Stop::Error(e) => println!("error: {}", e),
+            Stop::Exited => println!("program exited"),
+            Stop::Limit => println!("stopped after {} instructions", RUN_LIMIT),
+        }
+        self.show_current(chip8);
+    }
+
+    fn show_current(&self, chip8: &Chip8) {
+        print!("{}", self.disassembly(chip8, chip8.pc() as usize, 1));
+        if chip8.is_waiting_for_key() {
+            println!("waiting for a key, use 'key <KEY> down' and 'key <KEY> up'");
+        }
+    }
+
+    fn disassembly(&self, chip8: &Chip8, mut address: usize, count: usize) -> String {
+        let mut listing = String::new();
+        for _ in 0..count {
+            let instruction = match Instruction::decode_at(chip8.memory(), address) {
+                Some(instruction) => instruction,
+                None => break,
+            };
+            let breakpoint = chip8.breakpoints.iter().any(|(_, breakpoint)| breakpoint.watch == Watch::Execute(address as u16));
+            let marker = match (address == chip8.pc() as usize, breakpoint) {
+                (true, _) => '>',
+                (false, true) => '*',
+                (false, false) => ' ',
+            };
+            listing += &format!("{} {:03X}: {}\n", marker, address, instruction);
+            address += instruction.size();
+        }
+        listing
+    }
+}
+
+/// 16 bytes per line, with the address in front.
+fn hex_dump(memory: &[u8], address: usize, length: usize) -> String {
+    let end = address.saturating_add(length).min(memory.len());
+    let mut dump = String::new();
+    let mut line = address;
+    while line < end {
+        let bytes: Vec<String> = memory[line..(line + 16).min(end)].iter().map(|byte| format!("{:02X}", byte)).collect();
+        dump += &format!("{:04X}: {}\n", line, bytes.join(" "));
+        line += 16;
+    }
+    dump
+}
+
+//an address inside the memory of `chip8`
+fn address_arg(chip8: &Chip8, arg: Option<&&str>) -> Result<usize, String> {
+    let address = hex_arg(arg)?;
+    if address >= chip8.memory().len() {
+        return Err(format!("address {:X} is out of range", address));
+    }
+    Ok(address)
+}
+
+fn hex_arg(arg: Option<&&str>) -> Result<usize, String> {
+    match arg {
+        Some(arg) => parse_hex(arg),
+        None => Err(String::from("missing argument")),
+    }
+}
+
+fn optional_hex(arg: Option<&&str>, default: usize) -> Result<usize, String> {
+    match arg {
+        Some(arg) => parse_hex(arg),
+        None => Ok(default),
+    }
+}
+
+fn parse_hex(arg: &str) -> Result<usize, String> {
+    let digits = arg.trim_start_matches("0x");
+    usize::from_str_radix(digits, 16).map_err(|_| format!("invalid hex number '{}'", arg))
+}
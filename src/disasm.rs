@@ -0,0 +1,165 @@
+use chip8::Mode;
+use instruction::Instruction;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Address the ROM is loaded to.
+const ROM_START: usize = 0x200;
+/// Data bytes per line of the listing.
+const DATA_PER_LINE: usize = 8;
+
+/// Disassembles `rom` into an Octo listing that assembles back to the same bytes.
+///
+/// Code is found by following jumps, calls and skips from 0x200, everything
+/// that is not reached is listed as data. Targets inside the ROM get labels.
+pub fn disassemble(rom: &[u8], mode: Mode) -> String {
+    let code = trace_code(rom, mode);
+    let end = ROM_START + rom.len();
+    let targets: BTreeSet<usize> = code.values()
+        .filter_map(|instruction| instruction.target())
+        .map(|address| address as usize)
+        .filter(|&address| address >= ROM_START && address < end)
+        .collect();
+    let calls: BTreeSet<usize> = code.values()
+        .filter_map(|instruction| match *instruction {
+            Instruction::Call(address) => Some(address as usize),
+            _ => None,
+        })
+        .collect();
+
+    //split the ROM into lines, data lines end at the next code or label
+    let mut lines = Vec::new();
+    let mut address = ROM_START;
+    while address < end {
+        if let Some(instruction) = code.get(&address) {
+            lines.push((address, Some(*instruction)));
+            address += instruction.size();
+            continue;
+        }
+        let start = address;
+        address += 1;
+        while address < end && address - start < DATA_PER_LINE
+            && !code.contains_key(&address) && !targets.contains(&address) {
+            address += 1;
+        }
+        lines.push((start, None));
+    }
+
+    let labels: BTreeMap<usize, String> = lines.iter()
+        .map(|&(address, _)| address)
+        .filter(|address| targets.contains(address))
+        .map(|address| {
+            let name = if calls.contains(&address) {
+                "sub"
+            } else if code.contains_key(&address) {
+                "label"
+            } else {
+                "data"
+            };
+            (address, format!("{}_{:03X}", name, address))
+        })
+        .collect();
+
+    let mut listing = String::new();
+    for (i, &(address, instruction)) in lines.iter().enumerate() {
+        if let Some(label) = labels.get(&address) {
+            listing += &format!(": {}\n", label);
+        }
+        let text = match instruction {
+            Some(instruction) => {
+                let label = instruction.target().and_then(|target| labels.get(&(target as usize)));
+                instruction.to_octo(label.map(|label| label.as_str()))
+            }
+            None => {
+                let next = lines.get(i + 1).map_or(end, |&(next, _)| next);
+                let bytes: Vec<String> = rom[address - ROM_START..next - ROM_START].iter()
+                    .map(|byte| format!("0x{:02X}", byte))
+                    .collect();
+                bytes.join(" ")
+            }
+        };
+        listing += &format!("\t{:<40} # {:03X}\n", text, address);
+    }
+    listing
+}
+
+/// Finds the instructions reachable from the start of the ROM.
+fn trace_code(rom: &[u8], mode: Mode) -> BTreeMap<usize, Instruction> {
+    let end = ROM_START + rom.len();
+    let decode = |address: usize| -> Option<Instruction> {
+        if address < ROM_START || address >= end {
+            return None;
+        }
+        Instruction::decode_at(rom, address - ROM_START)
+    };
+
+    let mut code = BTreeMap::new();
+    let mut pending = vec![ROM_START];
+    while let Some(address) = pending.pop() {
+        if code.contains_key(&address) {
+            continue;
+        }
+        let instruction = match decode(address) {
+            //0000 and invalid opcodes are most likely data reached by a wrong guess
+            Some(Instruction::MachineCode(0)) | Some(Instruction::Invalid(_)) | None => continue,
+            Some(Instruction::MachineCode(_)) if mode != Mode::Chip8 => continue,
+            Some(instruction) => instruction,
+        };
+        code.insert(address, instruction);
+        let next = address + instruction.size();
+        match instruction {
+            Instruction::Jump(target) => pending.push(target as usize),
+            Instruction::Call(target) => {
+                pending.push(next);
+                pending.push(target as usize);
+            }
+            //the target of BNNN depends on V[0], the return address on the stack
+            Instruction::JumpOffset(_) | Instruction::Return | Instruction::Exit => {}
+            Instruction::SkipEqualByte(..) | Instruction::SkipNotEqualByte(..)
+            | Instruction::SkipEqual(..) | Instruction::SkipNotEqual(..)
+            | Instruction::SkipKeyPressed(_) | Instruction::SkipKeyReleased(_) => {
+                let skipped = match decode(next) {
+                    Some(Instruction::LongIndex(_)) if mode == Mode::XoChip => 4,
+                    _ => 2,
+                };
+                pending.push(next);
+                pending.push(next + skipped);
+            }
+            _ => pending.push(next),
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::assemble;
+
+    const ROM: [u8; 12] = [0x22, 0x06, 0x12, 0x02, 0xAB, 0xCD, 0xA2, 0x0A, 0x00, 0xEE, 0xF0, 0x90];
+
+    #[test]
+    fn code_labels_and_data() {
+        let lines: Vec<String> = disassemble(&ROM, Mode::Chip8).lines()
+            .map(|line| line.split('#').next().unwrap().trim().to_string())
+            .collect();
+        assert_eq!(lines, vec![
+            ":call sub_206", ": label_202", "jump label_202", "0xAB 0xCD",
+            ": sub_206", "i := data_20A", "return", ": data_20A", "0xF0 0x90",
+        ]);
+    }
+
+    #[test]
+    fn listing_assembles_back() {
+        for &mode in [Mode::Chip8, Mode::SuperChip, Mode::XoChip].iter() {
+            assert_eq!(assemble(&disassemble(&ROM, mode)).unwrap().rom, ROM.to_vec());
+        }
+        //F000 NNNN is 4 bytes long, the skip in front of it skips all of them
+        let long = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x12, 0x00];
+        let listing = disassemble(&long, Mode::XoChip);
+        assert!(listing.contains("i := long 0x1234"), "{}", listing);
+        assert_eq!(assemble(&listing).unwrap().rom, long.to_vec());
+    }
+}
@@ -1,11 +1,28 @@
 extern crate sdl2;
 
+use overlay::{self, Line};
+
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+/// Scale of the panel font, each glyph cell is 6x8 pixels before scaling.
+const TEXT_SCALE: u32 = 2;
+pub const CELL_WIDTH: u32 = 6 * TEXT_SCALE;
+pub const CELL_HEIGHT: u32 = 8 * TEXT_SCALE;
+/// Width of the debug panel right of the screen.
+pub const PANEL_WIDTH: u32 = overlay::COLUMNS as u32 * CELL_WIDTH + 2 * CELL_WIDTH;
+pub const PANEL_BACKGROUND: Color = Color { r: 0x20, g: 0x20, b: 0x20, a: 0xFF };
+pub const TEXT: Color = Color { r: 0xC0, g: 0xC0, b: 0xC0, a: 0xFF };
+pub const HIGHLIGHT: Color = Color { r: 0xFF, g: 0xD0, b: 0x00, a: 0xFF };
 
 pub struct Display {
     canvas: sdl2::render::Canvas<sdl2::video::Window>,
     pub sdl_context: sdl2::Sdl,
+    /// Colours of the pixel values 0 to 3, i.e. no plane, plane 1, plane 2 and both planes.
+    pub palette: [Color; 4],
+    panel: bool,
 }
 
 impl Display {
@@ -23,22 +40,98 @@ impl Display {
         Display {
             canvas,
             sdl_context,
+            palette: [
+                Color::RGB(0,0,0),
+                Color::RGB(255,255,255),
+                Color::RGB(255,102,0),
+                Color::RGB(102,34,0),
+            ],
+            panel: false,
         }
     }
     
-    pub fn render(&mut self, display: [bool; 64*32]) {
-        self.canvas.set_draw_color(Color::RGB(0,0,0));
+    /// Sets the palette from 0xRRGGBB values.
+    pub fn set_palette(&mut self, palette: [u32; 4]) {
+        for (color, rgb) in self.palette.iter_mut().zip(palette.iter()) {
+            *color = Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8);
+        }
+    }
+
+    /// Shows or hides the debug panel, the window grows or shrinks by its width.
+    pub fn set_panel(&mut self, panel: bool) {
+        if panel == self.panel {
+            return;
+        }
+        self.panel = panel;
+        let window = self.canvas.window_mut();
+        let (width, height) = window.size();
+        let width = if panel { width + PANEL_WIDTH } else { width.saturating_sub(PANEL_WIDTH).max(1) };
+        if let Err(e) = window.set_size(width, height) {
+            eprintln!("Error while resizing the window: {}", e);
+        }
+    }
+
+    pub fn has_panel(&self) -> bool {
+        self.panel
+    }
+
+    /// Number of text lines that fit into the panel.
+    pub fn panel_rows(&self) -> usize {
+        let (_, window_height) = self.canvas.output_size().expect("Error while reading window size!");
+        (window_height.saturating_sub(CELL_HEIGHT) / CELL_HEIGHT) as usize
+    }
+
+    /// Draws `display`, `width` pixels per row, scaled to fill the window.
+    /// Pixels are indices into the palette. `panel` is drawn right of it if the panel is shown.
+    pub fn render(&mut self, display: &[u8], width: usize, height: usize, panel: &[Line]) {
+        let (mut window_width, window_height) = self.canvas.output_size().expect("Error while reading window size!");
+        if self.panel {
+            window_width = window_width.saturating_sub(PANEL_WIDTH);
+        }
+        let scale = (window_width / width as u32).min(window_height / height as u32).max(1);
+
+        self.canvas.set_draw_color(self.palette[0]);
         self.canvas.clear();
 
-        self.canvas.set_draw_color(Color::RGB(255,255,255));
-        for y in 0..32 {
-            for x in 0..64 {
-                if display[y*64+x] {
-                    self.canvas.fill_rect(Rect::new((x * 10) as i32,(y * 10) as i32,10,10)).expect("Error while drawing rectangle!");
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = display[y*width+x] as usize & 0x03;
+                if pixel != 0 {
+                    self.canvas.set_draw_color(self.palette[pixel]);
+                    self.canvas.fill_rect(Rect::new(x as i32 * scale as i32,y as i32 * scale as i32,scale,scale)).expect("Error while drawing rectangle!");
                 }
             } 
         } 
+        if self.panel {
+            self.canvas.set_draw_color(PANEL_BACKGROUND);
+            self.canvas.fill_rect(Rect::new(window_width as i32, 0, PANEL_WIDTH, window_height)).expect("Error while drawing rectangle!");
+            for (row, line) in panel.iter().enumerate() {
+                let mut x = window_width as i32 + CELL_WIDTH as i32;
+                let y = (row as u32 * CELL_HEIGHT + CELL_HEIGHT / 2) as i32;
+                for &(ref text, highlight) in line.0.iter() {
+                    draw_text(&mut self.canvas, x, y, text, if highlight { HIGHLIGHT } else { TEXT });
+                    x += (text.chars().count() as u32 * CELL_WIDTH) as i32;
+                }
+            }
+        }
         self.canvas.present();
     }
 
 }
+
+/// Draws `text` with the panel font, the top left corner at `x`, `y`.
+pub fn draw_text(canvas: &mut Canvas<Window>, x: i32, y: i32, text: &str, color: Color) {
+    let mut pixels = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        let left = x + (i as u32 * CELL_WIDTH) as i32;
+        for (column, bits) in overlay::glyph(c).iter().enumerate() {
+            for row in 0..7 {
+                if bits & (1 << row) != 0 {
+                    pixels.push(Rect::new(left + (column as u32 * TEXT_SCALE) as i32, y + (row * TEXT_SCALE) as i32, TEXT_SCALE, TEXT_SCALE));
+                }
+            }
+        }
+    }
+    canvas.set_draw_color(color);
+    canvas.fill_rects(&pixels).expect("Error while drawing rectangle!");
+}
@@ -0,0 +1,56 @@
+use cdp1802::Cdp1802Error;
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+/// Errors raised by the interpreter while loading or running a program.
+///
+/// Addresses are the CHIP-8 addresses of the faulting instruction (`pc`)
+/// or the memory location that was accessed (`address`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The opcode does not decode to any known instruction.
+    InvalidOpcode { opcode: u16, pc: u16 },
+    /// 2NNN was executed with all stack levels in use.
+    StackOverflow { pc: u16 },
+    /// 00EE was executed with an empty stack.
+    StackUnderflow { pc: u16 },
+    /// An instruction read or wrote memory outside of the address space.
+    MemoryOutOfBounds { address: usize, pc: u16 },
+    /// A 0NNN machine code routine failed.
+    MachineCode { pc: u16, error: Cdp1802Error },
+    /// The ROM does not fit into memory after the interpreter area.
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chip8Error::InvalidOpcode { opcode, pc } =>
+                write!(f, "invalid opcode {:04X} at {:03X}", opcode, pc),
+            Chip8Error::StackOverflow { pc } =>
+                write!(f, "stack overflow at {:03X}", pc),
+            Chip8Error::StackUnderflow { pc } =>
+                write!(f, "stack underflow at {:03X}", pc),
+            Chip8Error::MemoryOutOfBounds { address, pc } =>
+                write!(f, "memory access out of bounds at {:03X}: address {:X}", pc, address),
+            Chip8Error::MachineCode { pc, ref error } =>
+                write!(f, "machine code routine called at {:03X} failed: {}", pc, error),
+            Chip8Error::RomTooLarge { size, max } =>
+                write!(f, "ROM too large: {} bytes, at most {} bytes fit into memory", size, max),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for Chip8Error {}
+
+/// What the interpreter does when an instruction fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorMode {
+    /// Halt on the first error, `pc` stays at the faulting instruction.
+    Strict,
+    /// Report the error, skip the faulting instruction and continue.
+    Lenient,
+}
@@ -0,0 +1,155 @@
+use options::Options;
+use recorder::Recorder;
+use tracer::Tracer;
+
+use rip8::{Chip8, InputScript, Keypad};
+
+use std::fs::File;
+use std::io::prelude::*;
+
+/// Exit code of a run without errors.
+pub const EXIT_OK: i32 = 0;
+/// Exit code if the program raised an error.
+pub const EXIT_ERROR: i32 = 1;
+/// Exit code if the run could not be set up, e.g. because of an unreadable file.
+pub const EXIT_USAGE: i32 = 2;
+
+/// Frames run if neither `--frames` nor `--cycles` is given, 10 seconds.
+const DEFAULT_FRAMES: u64 = 600;
+
+/// Runs `chip8` without a window and returns the exit code for the process.
+///
+/// `keypad` holds the keys pressed at the start, e.g. restored from a save state.
+/// A replayed movie runs to its end unless `--frames` or `--cycles` is given.
+///
+/// The final register state is printed to stdout, the framebuffer is written
+/// to the screenshot file if requested.
+pub fn run(chip8: &mut Chip8, mut keypad: Keypad, recorder: &mut Recorder, options: &Options) -> i32 {
+    let mut tracer = match Tracer::new(options) {
+        Ok(tracer) => tracer,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
+    };
+    let script = match options.input_script {
+        Some(ref path) => match read_script(path) {
+            Ok(script) => script,
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_USAGE;
+            }
+        },
+        None => InputScript::default(),
+    };
+
+    let cycles_per_frame = options.cycles_per_frame as u64;
+    let total_cycles = match (options.cycles, options.frames) {
+        (Some(cycles), _) => Some(cycles),
+        (None, Some(frames)) => frames.checked_mul(cycles_per_frame),
+        (None, None) => recorder.replay_len().unwrap_or(DEFAULT_FRAMES).checked_mul(cycles_per_frame),
+    };
+    let total_cycles = match total_cycles {
+        Some(total_cycles) => total_cycles,
+        None => {
+            eprintln!("too many frames, at most {} instructions can be run", u64::MAX);
+            return EXIT_USAGE;
+        }
+    };
+
+    let mut exit_code = EXIT_OK;
+    let mut cycles = 0;
+    let mut frame = 0;
+    while cycles < total_cycles && !chip8.has_exited() {
+        script.apply(frame, &mut keypad);
+        let frame_cycles = cycles_per_frame.min(total_cycles - cycles);
+        let keys = recorder.next_frame(&keypad);
+        //errors are reported by the tracer
+        let result = chip8.run_frame(&keys, frame_cycles as u32);
+        if let Err(e) = tracer.write(chip8) {
+            eprintln!("{}", e);
+            return EXIT_USAGE;
+        }
+        if result.is_err() {
+            exit_code = EXIT_ERROR;
+            if chip8.halted().is_some() {
+                break;
+            }
+        }
+        cycles += frame_cycles;
+        frame += 1;
+    }
+
+    print!("{}", register_dump(chip8));
+    println!("frames: {}", frame);
+    if let Some(seed) = options.seed {
+        println!("seed: {}", seed);
+    }
+
+    if let Err(e) = recorder.save().and_then(|_| tracer.finish()) {
+        eprintln!("{}", e);
+        return EXIT_USAGE;
+    }
+    if let Some(ref path) = options.screenshot {
+        if let Err(e) = write_screenshot(chip8, path) {
+            eprintln!("Error while writing {}: {}", path, e);
+            return EXIT_USAGE;
+        }
+    }
+    exit_code
+}
+
+fn read_script(path: &str) -> Result<InputScript, String> {
+    let mut script = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut script))
+        .map_err(|e| format!("Error while reading {}: {}", path, e))?;
+    InputScript::parse(&script).map_err(|e| e.to_string())
+}
+
+/// Formats pc, I, the registers, the stack and the timers, one per line.
+pub fn register_dump(chip8: &Chip8) -> String {
+    let mut dump = format!("pc: {:03X}\nI: {:03X}\n", chip8.pc(), chip8.index());
+    for (x, value) in chip8.registers().iter().enumerate() {
+        dump += &format!("V{:X}: {:02X}\n", x, value);
+    }
+    let stack: Vec<String> = chip8.stack().iter().map(|address| format!("{:03X}", address)).collect();
+    dump += &format!("stack: [{}]\n", stack.join(", "));
+    dump += &format!("delay timer: {}\nsound timer: {}\n", chip8.delay_timer(), chip8.sound_timer());
+    dump
+}
+
+/// Writes the framebuffer as plain PBM if `path` ends with .pbm, as ASCII art otherwise.
+fn write_screenshot(chip8: &Chip8, path: &str) -> ::std::io::Result<()> {
+    let image = if path.ends_with(".pbm") {
+        pbm(chip8.screen(), chip8.width(), chip8.height())
+    } else {
+        ascii(chip8.screen(), chip8.width())
+    };
+    File::create(path)?.write_all(image.as_bytes())
+}
+
+/// Plain (P1) PBM image, lit pixels are black.
+pub fn pbm(screen: &[u8], width: usize, height: usize) -> String {
+    let mut image = format!("P1\n{} {}\n", width, height);
+    for row in screen.chunks(width) {
+        let pixels: Vec<&str> = row.iter().map(|&pixel| if pixel != 0 { "1" } else { "0" }).collect();
+        image += &pixels.join(" ");
+        image.push('\n');
+    }
+    image
+}
+
+/// One line per row, `#` for lit and `.` for dark pixels, digits for XO-CHIP plane colours.
+pub fn ascii(screen: &[u8], width: usize) -> String {
+    let mut image = String::new();
+    for row in screen.chunks(width) {
+        image.extend(row.iter().map(|&pixel| match pixel {
+            0 => '.',
+            1 => '#',
+            _ => (b'0' + pixel) as char,
+        }));
+        image.push('\n');
+    }
+    image
+}
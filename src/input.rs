@@ -0,0 +1,9 @@
+/// Source of keypad state for the interpreter.
+///
+/// Keys are numbered like on the COSMAC VIP hex keypad (0x0 to 0xF).
+/// The frontend decides where the state comes from, so the core never
+/// has to know about windows or event loops.
+pub trait Input {
+    /// Returns true if `key` is currently held down.
+    fn is_pressed(&self, key: u8) -> bool;
+}
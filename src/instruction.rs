@@ -0,0 +1,329 @@
+use alloc::string::String;
+use core::fmt;
+
+/// A decoded CHIP-8, SUPER-CHIP or XO-CHIP instruction.
+///
+/// `x` and `y` are register numbers, `nn` immediate bytes and `nnn` addresses.
+/// Decoding does not depend on the mode: the SUPER-CHIP opcodes are decoded
+/// even though plain CHIP-8 runs them as machine code routines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0NNN: run the CDP1802 routine at NNN.
+    MachineCode(u16),
+    /// 00E0
+    Clear,
+    /// 00EE
+    Return,
+    /// 00CN
+    ScrollDown(u8),
+    /// 00FB
+    ScrollRight,
+    /// 00FC
+    ScrollLeft,
+    /// 00FD
+    Exit,
+    /// 00FE
+    Lores,
+    /// 00FF
+    Hires,
+    /// 1NNN
+    Jump(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN: skip if V[X] == NN.
+    SkipEqualByte(u8, u8),
+    /// 4XNN: skip if V[X] != NN.
+    SkipNotEqualByte(u8, u8),
+    /// 5XY0: skip if V[X] == V[Y].
+    SkipEqual(u8, u8),
+    /// 5XY2: store V[X] to V[Y] at I.
+    SaveRange(u8, u8),
+    /// 5XY3: load V[X] to V[Y] from I.
+    LoadRange(u8, u8),
+    /// 6XNN
+    SetByte(u8, u8),
+    /// 7XNN
+    AddByte(u8, u8),
+    /// 8XY0
+    Set(u8, u8),
+    /// 8XY1
+    Or(u8, u8),
+    /// 8XY2
+    And(u8, u8),
+    /// 8XY3
+    Xor(u8, u8),
+    /// 8XY4
+    Add(u8, u8),
+    /// 8XY5: V[X] -= V[Y].
+    Sub(u8, u8),
+    /// 8XY6
+    ShiftRight(u8, u8),
+    /// 8XY7: V[X] = V[Y] - V[X].
+    SubReverse(u8, u8),
+    /// 8XYE
+    ShiftLeft(u8, u8),
+    /// 9XY0: skip if V[X] != V[Y].
+    SkipNotEqual(u8, u8),
+    /// ANNN
+    SetIndex(u16),
+    /// BNNN
+    JumpOffset(u16),
+    /// CXNN
+    Random(u8, u8),
+    /// DXYN
+    Draw(u8, u8, u8),
+    /// EX9E: skip if key V[X] is pressed.
+    SkipKeyPressed(u8),
+    /// EXA1: skip if key V[X] is not pressed.
+    SkipKeyReleased(u8),
+    /// F000 NNNN: the address is the following word, see `decode_at`.
+    LongIndex(u16),
+    /// FN01: select the drawing planes N.
+    Plane(u8),
+    /// F002
+    Audio,
+    /// FX07
+    GetDelay(u8),
+    /// FX0A
+    WaitKey(u8),
+    /// FX15
+    SetDelay(u8),
+    /// FX18
+    SetSound(u8),
+    /// FX1E
+    AddIndex(u8),
+    /// FX29
+    Font(u8),
+    /// FX30
+    BigFont(u8),
+    /// FX33
+    Bcd(u8),
+    /// FX3A
+    Pitch(u8),
+    /// FX55
+    Save(u8),
+    /// FX65
+    Load(u8),
+    /// FX75
+    SaveFlags(u8),
+    /// FX85
+    LoadFlags(u8),
+    /// Any opcode that is not an instruction.
+    Invalid(u16),
+}
+
+impl Instruction {
+    /// Decodes a single opcode. F000 is decoded as `LongIndex(0)`.
+    pub fn decode(opcode: u16) -> Instruction {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+        match opcode & 0xF000 {
+            0x0000 => match opcode {
+                0x00E0 => Instruction::Clear,
+                0x00EE => Instruction::Return,
+                0x00C0 ..= 0x00CF => Instruction::ScrollDown(n),
+                0x00FB => Instruction::ScrollRight,
+                0x00FC => Instruction::ScrollLeft,
+                0x00FD => Instruction::Exit,
+                0x00FE => Instruction::Lores,
+                0x00FF => Instruction::Hires,
+                _ => Instruction::MachineCode(nnn),
+            },
+            0x1000 => Instruction::Jump(nnn),
+            0x2000 => Instruction::Call(nnn),
+            0x3000 => Instruction::SkipEqualByte(x, nn),
+            0x4000 => Instruction::SkipNotEqualByte(x, nn),
+            0x5000 => match n {
+                0x0 => Instruction::SkipEqual(x, y),
+                0x2 => Instruction::SaveRange(x, y),
+                0x3 => Instruction::LoadRange(x, y),
+                _ => Instruction::Invalid(opcode),
+            },
+            0x6000 => Instruction::SetByte(x, nn),
+            0x7000 => Instruction::AddByte(x, nn),
+            0x8000 => match n {
+                0x0 => Instruction::Set(x, y),
+                0x1 => Instruction::Or(x, y),
+                0x2 => Instruction::And(x, y),
+                0x3 => Instruction::Xor(x, y),
+                0x4 => Instruction::Add(x, y),
+                0x5 => Instruction::Sub(x, y),
+                0x6 => Instruction::ShiftRight(x, y),
+                0x7 => Instruction::SubReverse(x, y),
+                0xE => Instruction::ShiftLeft(x, y),
+                _ => Instruction::Invalid(opcode),
+            },
+            0x9000 if n == 0 => Instruction::SkipNotEqual(x, y),
+            0xA000 => Instruction::SetIndex(nnn),
+            0xB000 => Instruction::JumpOffset(nnn),
+            0xC000 => Instruction::Random(x, nn),
+            0xD000 => Instruction::Draw(x, y, n),
+            0xE000 => match nn {
+                0x9E => Instruction::SkipKeyPressed(x),
+                0xA1 => Instruction::SkipKeyReleased(x),
+                _ => Instruction::Invalid(opcode),
+            },
+            0xF000 => match nn {
+                0x00 if x == 0 => Instruction::LongIndex(0),
+                0x01 => Instruction::Plane(x),
+                0x02 if x == 0 => Instruction::Audio,
+                0x07 => Instruction::GetDelay(x),
+                0x0A => Instruction::WaitKey(x),
+                0x15 => Instruction::SetDelay(x),
+                0x18 => Instruction::SetSound(x),
+                0x1E => Instruction::AddIndex(x),
+                0x29 => Instruction::Font(x),
+                0x30 => Instruction::BigFont(x),
+                0x33 => Instruction::Bcd(x),
+                0x3A => Instruction::Pitch(x),
+                0x55 => Instruction::Save(x),
+                0x65 => Instruction::Load(x),
+                0x75 => Instruction::SaveFlags(x),
+                0x85 => Instruction::LoadFlags(x),
+                _ => Instruction::Invalid(opcode),
+            },
+            _ => Instruction::Invalid(opcode),
+        }
+    }
+
+    /// Decodes the instruction at `address`, including the operand of F000 NNNN.
+    ///
+    /// Returns None if the instruction does not fit into `memory`.
+    pub fn decode_at(memory: &[u8], address: usize) -> Option<Instruction> {
+        let word = |address: usize| -> Option<u16> {
+            Some((*memory.get(address)? as u16) << 8 | *memory.get(address + 1)? as u16)
+        };
+        match Instruction::decode(word(address)?) {
+            Instruction::LongIndex(_) => word(address + 2).map(Instruction::LongIndex),
+            instruction => Some(instruction),
+        }
+    }
+
+    /// Length of the instruction in bytes.
+    pub fn size(&self) -> usize {
+        match *self {
+            Instruction::LongIndex(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Address operand of jumps, calls and I loads.
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Jump(nnn) | Instruction::Call(nnn) | Instruction::JumpOffset(nnn)
+            | Instruction::SetIndex(nnn) | Instruction::LongIndex(nnn) => Some(nnn),
+            _ => None,
+        }
+    }
+
+    /// Formats the instruction in Octo syntax with `label` in place of the address operand.
+    pub fn to_octo(&self, label: Option<&str>) -> String {
+        let target = match (label, self.target()) {
+            (Some(label), _) => String::from(label),
+            (None, Some(address)) => format!("0x{:03X}", address),
+            (None, None) => String::new(),
+        };
+        match *self {
+            Instruction::MachineCode(nnn) => format!("0x{:02X} 0x{:02X}", nnn >> 8, nnn & 0xFF),
+            Instruction::Clear => String::from("clear"),
+            Instruction::Return => String::from("return"),
+            Instruction::ScrollDown(n) => format!("scroll-down {}", n),
+            Instruction::ScrollRight => String::from("scroll-right"),
+            Instruction::ScrollLeft => String::from("scroll-left"),
+            Instruction::Exit => String::from("exit"),
+            Instruction::Lores => String::from("lores"),
+            Instruction::Hires => String::from("hires"),
+            Instruction::Jump(_) => format!("jump {}", target),
+            Instruction::Call(_) => format!(":call {}", target),
+            //Octo names skips after the condition under which the next instruction runs
+            Instruction::SkipEqualByte(x, nn) => format!("if v{:X} != 0x{:02X} then", x, nn),
+            Instruction::SkipNotEqualByte(x, nn) => format!("if v{:X} == 0x{:02X} then", x, nn),
+            Instruction::SkipEqual(x, y) => format!("if v{:X} != v{:X} then", x, y),
+            Instruction::SaveRange(x, y) => format!("save v{:X} - v{:X}", x, y),
+            Instruction::LoadRange(x, y) => format!("load v{:X} - v{:X}", x, y),
+            Instruction::SetByte(x, nn) => format!("v{:X} := 0x{:02X}", x, nn),
+            Instruction::AddByte(x, nn) => format!("v{:X} += 0x{:02X}", x, nn),
+            Instruction::Set(x, y) => format!("v{:X} := v{:X}", x, y),
+            Instruction::Or(x, y) => format!("v{:X} |= v{:X}", x, y),
+            Instruction::And(x, y) => format!("v{:X} &= v{:X}", x, y),
+            Instruction::Xor(x, y) => format!("v{:X} ^= v{:X}", x, y),
+            Instruction::Add(x, y) => format!("v{:X} += v{:X}", x, y),
+            Instruction::Sub(x, y) => format!("v{:X} -= v{:X}", x, y),
+            Instruction::ShiftRight(x, y) => format!("v{:X} >>= v{:X}", x, y),
+            Instruction::SubReverse(x, y) => format!("v{:X} =- v{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => format!("v{:X} <<= v{:X}", x, y),
+            Instruction::SkipNotEqual(x, y) => format!("if v{:X} == v{:X} then", x, y),
+            Instruction::SetIndex(_) => format!("i := {}", target),
+            Instruction::JumpOffset(_) => format!("jump0 {}", target),
+            Instruction::Random(x, nn) => format!("v{:X} := random 0x{:02X}", x, nn),
+            Instruction::Draw(x, y, n) => format!("sprite v{:X} v{:X} {}", x, y, n),
+            Instruction::SkipKeyPressed(x) => format!("if v{:X} -key then", x),
+            Instruction::SkipKeyReleased(x) => format!("if v{:X} key then", x),
+            Instruction::LongIndex(_) => format!("i := long {}", target),
+            Instruction::Plane(n) => format!("plane {}", n),
+            Instruction::Audio => String::from("audio"),
+            Instruction::GetDelay(x) => format!("v{:X} := delay", x),
+            Instruction::WaitKey(x) => format!("v{:X} := key", x),
+            Instruction::SetDelay(x) => format!("delay := v{:X}", x),
+            Instruction::SetSound(x) => format!("buzzer := v{:X}", x),
+            Instruction::AddIndex(x) => format!("i += v{:X}", x),
+            Instruction::Font(x) => format!("i := hex v{:X}", x),
+            Instruction::BigFont(x) => format!("i := bighex v{:X}", x),
+            Instruction::Bcd(x) => format!("bcd v{:X}", x),
+            Instruction::Pitch(x) => format!("pitch := v{:X}", x),
+            Instruction::Save(x) => format!("save v{:X}", x),
+            Instruction::Load(x) => format!("load v{:X}", x),
+            Instruction::SaveFlags(x) => format!("saveflags v{:X}", x),
+            Instruction::LoadFlags(x) => format!("loadflags v{:X}", x),
+            Instruction::Invalid(opcode) => format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_octo(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::assemble;
+
+    #[test]
+    fn every_opcode_assembles_back() {
+        for opcode in 0..=0xFFFF_u16 {
+            let instruction = Instruction::decode(opcode);
+            if let Instruction::LongIndex(_) = instruction {
+                continue;
+            }
+            let text = instruction.to_octo(None);
+            let rom = assemble(&text).unwrap_or_else(|e| panic!("{:04X} '{}': {}", opcode, text, e)).rom;
+            assert_eq!(rom, opcode.to_be_bytes(), "{:04X} '{}'", opcode, text);
+        }
+    }
+
+    #[test]
+    fn long_index_takes_the_next_word() {
+        let memory = [0xF0, 0x00, 0x12, 0x34];
+        let instruction = Instruction::decode_at(&memory, 0).unwrap();
+        assert_eq!(instruction, Instruction::LongIndex(0x1234));
+        assert_eq!(instruction.size(), 4);
+        assert_eq!(assemble(&instruction.to_octo(None)).unwrap().rom, memory);
+        assert_eq!(Instruction::decode_at(&memory[..3], 0), None);
+    }
+
+    #[test]
+    fn sizes_and_targets() {
+        assert_eq!(Instruction::decode(0x00E0).size(), 2);
+        assert_eq!(Instruction::decode(0x2345).target(), Some(0x345));
+        assert_eq!(Instruction::decode(0xB123).target(), Some(0x123));
+        assert_eq!(Instruction::decode(0x6123).target(), None);
+        assert_eq!(Instruction::decode(0x5121), Instruction::Invalid(0x5121));
+        assert_eq!(Instruction::decode(0x8AB7), Instruction::SubReverse(0xA, 0xB));
+    }
+}
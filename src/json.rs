@@ -0,0 +1,370 @@
+use std::fmt;
+
+/// A JSON value, just enough for the messages of the debug adapter protocol.
+///
+/// Object members keep their order, lookups are linear.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+static NULL: Json = Json::Null;
+
+/// Arrays and objects nested deeper than this are rejected instead of overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+impl Json {
+    /// Builds an object from its members.
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(members.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { text: text.as_bytes(), position: 0, depth: 0 };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position != parser.text.len() {
+            return Err(format!("unexpected data at offset {}", parser.position));
+        }
+        Ok(value)
+    }
+
+    /// Returns the member `key` of an object, `Null` if there is none.
+    pub fn get(&self, key: &str) -> &Json {
+        match *self {
+            Json::Object(ref members) => members.iter()
+                .find(|&(name, _)| name == key)
+                .map_or(&NULL, |(_, value)| value),
+            _ => &NULL,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Json::Number(n) if n.fract() == 0.0 => Some(n as i64),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of an array, nothing for other values.
+    pub fn as_array(&self) -> &[Json] {
+        match *self {
+            Json::Array(ref elements) => elements,
+            _ => &[],
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Json {
+    fn from(s: &'a str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(n: i32) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(elements: Vec<Json>) -> Json {
+        Json::Array(elements)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(ref s) => write_string(f, s),
+            Json::Array(ref elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(ref members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    position: usize,
+    //arrays and objects around the current value
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn whitespace(&mut self) {
+        while self.position < self.text.len() && (self.text[self.position] as char).is_ascii_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.position).cloned()
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if self.text[self.position..].starts_with(literal.as_bytes()) {
+            self.position += literal.len();
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at offset {}", literal, self.position))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.whitespace();
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Json::Null),
+            Some(b't') => self.expect("true").map(|_| Json::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') | Some(b'{') => {
+                if self.depth == MAX_DEPTH {
+                    return Err(format!("nested deeper than {} levels at offset {}", MAX_DEPTH, self.position));
+                }
+                self.depth += 1;
+                let value = if self.peek() == Some(b'[') { self.array() } else { self.object() };
+                self.depth -= 1;
+                value
+            }
+            Some(b'-') | Some(b'0'..=b'9') => {
+                let start = self.position;
+                while let Some(b'0'..=b'9') | Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e') | Some(b'E') = self.peek() {
+                    self.position += 1;
+                }
+                let number = String::from_utf8_lossy(&self.text[start..self.position]);
+                number.parse().map(Json::Number).map_err(|_| format!("invalid number '{}'", number))
+            }
+            _ => Err(format!("unexpected character at offset {}", self.position)),
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.position += 1;
+        let mut elements = Vec::new();
+        self.whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Json::Array(elements));
+        }
+        loop {
+            elements.push(self.value()?);
+            self.whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(elements));
+                }
+                _ => return Err(format!("expected ',' or ']' at offset {}", self.position)),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.position += 1;
+        let mut members = Vec::new();
+        self.whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(":")?;
+            members.push((key, self.value()?));
+            self.whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(format!("expected ',' or '}}' at offset {}", self.position)),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut bytes = Vec::new();
+        loop {
+            let byte = self.peek().ok_or_else(|| String::from("unterminated string"))?;
+            self.position += 1;
+            match byte {
+                b'"' => return String::from_utf8(bytes).map_err(|_| String::from("invalid UTF-8 in string")),
+                b'\\' => {
+                    let escape = self.peek().ok_or_else(|| String::from("unterminated string"))?;
+                    self.position += 1;
+                    let c = match escape {
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => match self.code_unit()? {
+                            high @ 0xD800..=0xDBFF if self.text[self.position..].starts_with(b"\\u") => {
+                                let start = self.position;
+                                self.position += 2;
+                                match self.code_unit()? {
+                                    low @ 0xDC00..=0xDFFF => {
+                                        std::char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).unwrap_or('\u{FFFD}')
+                                    }
+                                    //the next escape is read on its own
+                                    _ => {
+                                        self.position = start;
+                                        '\u{FFFD}'
+                                    }
+                                }
+                            }
+                            //lone surrogates become U+FFFD
+                            unit => std::char::from_u32(unit).unwrap_or('\u{FFFD}'),
+                        },
+                        other => other as char,
+                    };
+                    let mut buffer = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+    }
+
+    /// The four hex digits of a `\u` escape.
+    fn code_unit(&mut self) -> Result<u32, String> {
+        let digits = self.text.get(self.position..self.position + 4)
+            .filter(|digits| digits.iter().all(|digit| digit.is_ascii_hexdigit()))
+            .ok_or_else(|| format!("invalid escape at offset {}", self.position))?;
+        self.position += 4;
+        Ok(digits.iter().fold(0, |unit, &digit| unit << 4 | (digit as char).to_digit(16).unwrap_or(0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_serialize() {
+        let text = r#"{"seq":1,"type":"request","arguments":{"lines":[1,-2,3.5,1e3],"ok":true,"none":null,"empty":[],"nested":{}}}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.get("seq").as_i64(), Some(1));
+        assert_eq!(json.get("arguments").get("lines").as_array()[1].as_i64(), Some(-2));
+        assert_eq!(json.get("arguments").get("lines").as_array()[2].as_i64(), None);
+        assert_eq!(json.get("arguments").get("ok").as_bool(), Some(true));
+        assert_eq!(json.get("missing"), &Json::Null);
+        assert_eq!(json.to_string(), text.replace("1e3", "1000"));
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+        assert_eq!(Json::parse(" [ 1 , { \"a\" : [ ] } ] \n").unwrap().to_string(), r#"[1,{"a":[]}]"#);
+    }
+
+    #[test]
+    fn escapes() {
+        let json = Json::parse(r#""quote \" backslash \\ slash \/ \n\r\t\b\f \u0041\u00e9\u20AC""#).unwrap();
+        assert_eq!(json.as_str(), Some("quote \" backslash \\ slash / \n\r\t\u{8}\u{c} Aé€"));
+        assert_eq!(json.to_string(), r#""quote \" backslash \\ slash / \n\r\t\u0008\u000c Aé€""#);
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+        assert_eq!(Json::parse("\"\u{1F600}\"").unwrap().to_string(), "\"\u{1F600}\"");
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_eq!(Json::parse(r#""\ud83d\ude00""#), Ok(Json::from("\u{1F600}")));
+        assert_eq!(Json::parse(r#""\uD834\uDD1E!""#), Ok(Json::from("\u{1D11E}!")));
+        //lone surrogates
+        assert_eq!(Json::parse(r#""\ud83d""#), Ok(Json::from("\u{FFFD}")));
+        assert_eq!(Json::parse(r#""\ude00\ud83d""#), Ok(Json::from("\u{FFFD}\u{FFFD}")));
+        assert_eq!(Json::parse(r#""\ud83d\u0041""#), Ok(Json::from("\u{FFFD}A")));
+    }
+
+    #[test]
+    fn invalid_json_is_rejected() {
+        for text in ["", "{", "[1,]", "{\"a\" 1}", "{1:2}", "\"open", "\"\\u12\"", "\"\\u+123\"", "nul", "1 2", "--1", "[1}"].iter() {
+            assert!(Json::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Json::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Json::parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(Json::parse(&"[{\"a\":".repeat(100_000)).is_err());
+    }
+}
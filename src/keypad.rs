@@ -0,0 +1,52 @@
+use input::Input;
+
+/// State of the 16-key hex keypad.
+///
+/// Every key is tracked independently, so any number of keys can be held
+/// at the same time (e.g. both players in a two-player game).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Keypad {
+    keys: [bool; 16],
+}
+
+impl Keypad {
+    pub fn new() -> Keypad {
+        Keypad { keys: [false; 16] }
+    }
+
+    /// Marks `key` as held down.
+    pub fn press(&mut self, key: u8) {
+        self.set(key, true);
+    }
+
+    /// Marks `key` as released.
+    pub fn release(&mut self, key: u8) {
+        self.set(key, false);
+    }
+
+    /// Sets the state of `key`. Only the low nibble of `key` is used.
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        self.keys[(key & 0x0F) as usize] = pressed;
+    }
+
+    /// Returns the key state as a bit mask, bit N is set while key N is held.
+    pub fn bits(&self) -> u16 {
+        (0..16).filter(|&key| self.keys[key]).fold(0, |bits, key| bits | 1 << key)
+    }
+
+    /// Creates a keypad from a bit mask as returned by `bits`.
+    pub fn from_bits(bits: u16) -> Keypad {
+        let mut keypad = Keypad::new();
+        for key in 0..16 {
+            keypad.set(key, bits & 1 << key != 0);
+        }
+        keypad
+    }
+}
+
+impl Input for Keypad {
+    fn is_pressed(&self, key: u8) -> bool {
+        // keys outside the keypad range are never pressed
+        key < 16 && self.keys[key as usize]
+    }
+}
@@ -0,0 +1,60 @@
+//! CHIP-8, SUPER-CHIP and XO-CHIP interpreter.
+//!
+//! The core has no dependencies on a window system and builds without the
+//! standard library (`default-features = false`), only `alloc` is needed.
+//! A frontend feeds keypad state through the `Input` trait, runs one
+//! `Chip8::run_frame` per 60 Hz frame and draws `Chip8::screen`:
+//!
+//! ```no_run
+//! use rip8::{Chip8, Keypad, Mode};
+//!
+//! let rom = vec![0x00, 0xE0, 0x12, 0x02];
+//! let mut chip8 = Chip8::with_mode(rom, Mode::Chip8).unwrap();
+//! let mut keypad = Keypad::new();
+//! keypad.press(0x5);
+//! chip8.run_frame(&keypad, 10).unwrap();
+//! let (width, height) = (chip8.width(), chip8.height());
+//! assert_eq!(chip8.screen().len(), width * height);
+//! ```
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate core;
+
+pub mod assembler;
+pub mod audio;
+pub mod breakpoint;
+pub mod cdp1802;
+pub mod chip8;
+pub mod disasm;
+pub mod error;
+pub mod input;
+pub mod instruction;
+pub mod keypad;
+pub mod movie;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod script;
+pub mod state;
+pub mod trace;
+
+pub use assembler::{assemble, AsmError, Program};
+pub use audio::{Audio, NullAudio, Oscillator, Pattern, RecordingAudio, Tone, Waveform};
+pub use breakpoint::{Breakpoint, Breakpoints, Condition, Event, Hit, Register, Stop, Watch};
+pub use cdp1802::{Bus, Cdp1802, Cdp1802Error};
+pub use chip8::{Chip8, Mode, DISPLAY_HEIGHT, DISPLAY_WIDTH, PLANES};
+pub use disasm::disassemble;
+pub use error::{Chip8Error, ErrorMode};
+pub use input::Input;
+pub use instruction::Instruction;
+pub use keypad::Keypad;
+pub use movie::{rom_hash, Movie};
+pub use quirks::{LoadStore, Quirks};
+pub use rewind::Rewind;
+pub use rng::{Rng, SequenceRng, Xorshift, DEFAULT_SEED};
+pub use script::{InputScript, ScriptError};
+pub use state::{StateError, STATE_VERSION};
+pub use trace::{read_trace, Level, Message, Record, Step, Target, TraceFormat, Tracing};
@@ -1,84 +1,314 @@
+extern crate rip8;
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 
-use display::Display;
-use chip8::Chip8;
+use debugger::Debugger;
+use options::{Options, OptionsError};
+use recorder::Recorder;
+
+use rip8::{Chip8, Keypad, Xorshift};
 
 use std::env;
 use std::fs::File;
 use std::io::prelude::*;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-
-mod chip8;
+mod commands;
+mod dap;
+mod debugger;
+#[cfg(feature = "sdl")]
 mod display;
+mod headless;
+mod json;
+#[cfg(feature = "sdl")]
+mod memory_viewer;
+mod options;
+#[cfg(feature = "sdl")]
+mod overlay;
+mod recorder;
+#[cfg(feature = "sdl")]
+mod sdl_audio;
+#[cfg(feature = "sdl")]
+mod sdl_input;
+mod tracer;
 
-fn read_rom(path: String) -> Vec<u8> {
-    let mut f = File::open(&path).expect("Error while opening file.");
+fn read_file(path: &str) -> std::io::Result<Vec<u8>> {
+    let mut f = File::open(path)?;
     let mut rom = Vec::new();
-    f.read_to_end(&mut rom).expect("Error while reading file.");
-    rom
+    f.read_to_end(&mut rom)?;
+    Ok(rom)
 }
 
 fn main() {
-    let path: String = env::args().nth(1).unwrap(); 
-    let rom = read_rom(path);
-    
-    let mut chip8 = Chip8::new(rom, false);
-    let mut display = Display::new();
-
-    let mut event_pump = display.sdl_context.event_pump().unwrap();
-    
-    //variables for pressed keys
-    let mut key = 0x10;
-    let mut old_key = 0x10;
-    'main: loop {
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit{..} | Event::KeyDown {keycode: Some(Keycode::Escape), ..} => {
-                    break 'main
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|arg| arg.as_str()) {
+        Some("asm") => process::exit(commands::asm(&args[1..])),
+        Some("dap") => process::exit(dap::run(&args[1..])),
+        Some("disasm") => process::exit(commands::disasm(&args[1..])),
+        Some("trace-diff") => process::exit(commands::trace_diff(&args[1..])),
+        _ => {}
+    }
+
+    let debug = args.first().map(|arg| arg.as_str()) == Some("debug");
+    let args = if debug { args[1..].to_vec() } else { args };
+
+    let mut options = match Options::parse(args.into_iter()) {
+        Ok(options) => options,
+        Err(OptionsError::Help) => {
+            println!("{}", options::USAGE);
+            return;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(headless::EXIT_USAGE);
+        }
+    };
+    let rom = match read_file(&options.rom) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Error while reading {}: {}", options.rom, e);
+            process::exit(headless::EXIT_USAGE);
+        }
+    };
+
+    if options.load_state.is_some() && (options.record.is_some() || options.replay.is_some()) {
+        eprintln!("--load-state can not be combined with --record or --replay, movies start at power-on");
+        process::exit(headless::EXIT_USAGE);
+    }
+    let replay = match recorder::read_replay(&mut options, &rom) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(headless::EXIT_USAGE);
+        }
+    };
+    let seed = *options.seed.get_or_insert_with(time_seed);
+    let mut recorder = Recorder::new(replay, &options, &rom);
+
+    let mut chip8 = match Chip8::with_mode(rom, options.mode) {
+        Ok(chip8) => chip8,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(headless::EXIT_ERROR);
+        }
+    };
+    chip8.error_mode = options.error_mode;
+    chip8.quirks = options.quirks;
+    chip8.set_rng(Box::new(Xorshift::new(seed)));
+
+    let mut keypad = Keypad::new();
+    if let Some(ref path) = options.load_state {
+        match load_state_file(&mut chip8, path) {
+            Ok(saved_keypad) => keypad = saved_keypad,
+            Err(e) => {
+                eprintln!("Error while loading {}: {}", path, e);
+                process::exit(headless::EXIT_USAGE);
+            }
+        }
+    }
+
+    if debug {
+        process::exit(Debugger::new(keypad, options.cycles_per_frame).run(&mut chip8));
+    }
+    //the debugger prints errors itself and has no use for the records
+    chip8.tracing = options.tracing.clone();
+    if options.headless {
+        process::exit(headless::run(&mut chip8, keypad, &mut recorder, &options));
+    }
+    process::exit(window::run(&mut chip8, keypad, &mut recorder, &options));
+}
+
+/// Seed for runs without `--seed`, different on every start.
+fn time_seed() -> u32 {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    time.subsec_nanos() ^ time.as_secs() as u32
+}
+
+/// Restores the save state in `path`, returns the keys held when it was saved.
+fn load_state_file(chip8: &mut Chip8, path: &str) -> Result<Keypad, String> {
+    let state = read_file(path).map_err(|e| e.to_string())?;
+    chip8.load_state(&state).map_err(|e| e.to_string())
+}
+
+/// File of the save state slot `slot` next to the ROM.
+#[cfg(feature = "sdl")]
+fn slot_path(rom: &str, slot: u8) -> String {
+    format!("{}.state{}", rom, slot)
+}
+
+#[cfg(feature = "sdl")]
+mod window {
+    use display::Display;
+    use headless;
+    use memory_viewer::MemoryViewer;
+    use options::Options;
+    use overlay::{self, IpsCounter};
+    use sdl_audio::SdlAudio;
+    use recorder::Recorder;
+    use sdl_input::{Hotkey, SdlInput};
+    use tracer::Tracer;
+    use {load_state_file, slot_path};
+
+    use rip8::{Audio, Chip8, Keypad, NullAudio, Rewind};
+
+    use std::fs::File;
+    use std::io::prelude::*;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// Length of one frame, the timers run at 60 Hz.
+    const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    /// Runs `chip8` in an SDL window until it is closed, returns the exit code.
+    pub fn run(chip8: &mut Chip8, keypad: Keypad, recorder: &mut Recorder, options: &Options) -> i32 {
+        let mut tracer = match Tracer::new(options) {
+            Ok(tracer) => tracer,
+            Err(e) => {
+                eprintln!("{}", e);
+                return headless::EXIT_USAGE;
+            }
+        };
+        let mut display = Display::new();
+        display.set_palette(options.palette);
+
+        let mut input = SdlInput::new(display.sdl_context.event_pump().unwrap());
+        input.keypad = keypad;
+        let mut rewind = Rewind::new(options.rewind_seconds as usize * 60);
+        let mut audio: Box<dyn Audio> = if options.mute {
+            Box::new(NullAudio)
+        } else {
+            match SdlAudio::new(&display.sdl_context, options.tone) {
+                Ok(audio) => Box::new(audio),
+                Err(e) => {
+                    eprintln!("Audio disabled: {}", e);
+                    Box::new(NullAudio)
+                }
+            }
+        };
+
+        let mut ips = IpsCounter::new();
+        let mut memory_viewer: Option<MemoryViewer> = None;
+        let mut exit_code = headless::EXIT_OK;
+        let mut next_frame = Instant::now();
+        while !input.quit && !chip8.has_exited() {
+            input.poll();
+            for hotkey in input.hotkeys.clone() {
+                match hotkey {
+                    Hotkey::SaveState(slot) => save_slot(chip8, &input.keypad, &slot_path(&options.rom, slot)),
+                    Hotkey::LoadState(_) if recorder.is_active() => {
+                        eprintln!("Loading states is disabled while a movie is recorded or replayed");
+                    }
+                    Hotkey::LoadState(slot) => {
+                        load_slot(chip8, &slot_path(&options.rom, slot));
+                        rewind.clear();
+                    }
+                    Hotkey::ToggleOverlay => {
+                        let panel = !display.has_panel();
+                        display.set_panel(panel);
+                        chip8.draw_flag = true;
+                    }
+                    Hotkey::ToggleMemoryViewer if memory_viewer.is_some() => memory_viewer = None,
+                    Hotkey::ToggleMemoryViewer => match MemoryViewer::new(&display.sdl_context, chip8) {
+                        Ok(viewer) => memory_viewer = Some(viewer),
+                        Err(e) => eprintln!("Error while opening the memory viewer: {}", e),
+                    },
+                    Hotkey::CloseWindow(id) if memory_viewer.as_ref().map(|viewer| viewer.window_id()) == Some(id) => {
+                        memory_viewer = None;
+                    }
+                    Hotkey::CloseWindow(_) => input.quit = true,
+                    Hotkey::Click { window_id, x, y } => {
+                        if let Some(ref mut viewer) = memory_viewer {
+                            if viewer.window_id() == window_id {
+                                viewer.click(x, y);
+                            }
+                        }
+                    }
+                    Hotkey::Scroll(rows) => memory_viewer.iter_mut().for_each(|viewer| viewer.scroll(rows)),
+                    Hotkey::MoveSprite(bytes) => memory_viewer.iter_mut().for_each(|viewer| viewer.move_sprite(bytes, chip8.index())),
+                    Hotkey::FollowIndex => memory_viewer.iter_mut().for_each(|viewer| viewer.follow_index()),
+                }
+            }
+            if input.rewind {
+                //play backward one frame per frame, stay on the oldest state once the buffer is empty
+                if rewind.step_back(chip8) {
+                    recorder.step_back();
                 }
-                Event::KeyDown {keycode: Some(Keycode::Num1), ..} => {key = 0x00;},
-                Event::KeyDown {keycode: Some(Keycode::Num2), ..} => {key = 0x01;},
-                Event::KeyDown {keycode: Some(Keycode::Num3), ..} => {key = 0x02;},
-                Event::KeyDown {keycode: Some(Keycode::Num4), ..} => {key = 0x03;},
-                Event::KeyDown {keycode: Some(Keycode::Q), ..} => {key = 0x04;},
-                Event::KeyDown {keycode: Some(Keycode::W), ..} => {key = 0x05;},
-                Event::KeyDown {keycode: Some(Keycode::E), ..} => {key = 0x06;},
-                Event::KeyDown {keycode: Some(Keycode::R), ..} => {key = 0x07;},
-                Event::KeyDown {keycode: Some(Keycode::A), ..} => {key = 0x08;},
-                Event::KeyDown {keycode: Some(Keycode::S), ..} => {key = 0x09;},
-                Event::KeyDown {keycode: Some(Keycode::D), ..} => {key = 0x0A;},
-                Event::KeyDown {keycode: Some(Keycode::F), ..} => {key = 0x0B;},
-                Event::KeyDown {keycode: Some(Keycode::Y), ..} => {key = 0x0C;},
-                Event::KeyDown {keycode: Some(Keycode::X), ..} => {key = 0x0D;},
-                Event::KeyDown {keycode: Some(Keycode::C), ..} => {key = 0x0E;},
-                Event::KeyDown {keycode: Some(Keycode::V), ..} => {key = 0x0F;},
-                Event::KeyUp {keycode: Some(Keycode::Num1), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::Num2), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::Num3), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::Num4), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::Q), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::W), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::E), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::R), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::A), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::S), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::D), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::F), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::Y), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::X), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::C), ..} => {key = 0x10;},
-                Event::KeyUp {keycode: Some(Keycode::V), ..} => {key = 0x10;},
-                _ => {}
+            } else {
+                rewind.push(chip8);
+                let keypad = recorder.next_frame(&input.keypad);
+                let instructions = chip8.instructions();
+                //errors are reported by the tracer
+                let result = chip8.run_frame(&keypad, options.cycles_per_frame);
+                ips.add(chip8.instructions() - instructions);
+                if let Err(e) = tracer.write(chip8) {
+                    eprintln!("{}", e);
+                    exit_code = headless::EXIT_USAGE;
+                    break;
+                }
+                if result.is_err() && chip8.halted().is_some() {
+                    exit_code = headless::EXIT_ERROR;
+                    break;
+                }
+            }
+            audio.set_pattern(chip8.audio_pattern());
+            audio.set_playing(chip8.is_sound_playing());
+            //the panel changes with every instruction, the screen only when drawn to
+            if display.has_panel() {
+                let panel = overlay::lines(chip8, &input.keypad, ips.ips, display.panel_rows());
+                display.render(chip8.screen(), chip8.width(), chip8.height(), &panel);
+                chip8.draw_flag = false;
+            } else if chip8.draw_flag {
+                display.render(chip8.screen(), chip8.width(), chip8.height(), &[]);
+                chip8.draw_flag = false;
+            }
+            if let Some(ref mut viewer) = memory_viewer {
+                viewer.update(chip8.memory());
+                viewer.render(chip8);
+            }
+
+            //wait for the start of the next frame, unless we are already late
+            next_frame += FRAME;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
             }
         }
-        chip8.run_cycle(old_key, &mut event_pump);
-        if chip8.draw_flag {
-            display.render(chip8.display);
+        if let Err(e) = recorder.save().and_then(|_| tracer.finish()) {
+            eprintln!("{}", e);
+            return headless::EXIT_USAGE;
         }
-        if key != old_key {
-            old_key = key;
+        exit_code
+    }
+
+    fn save_slot(chip8: &Chip8, keypad: &Keypad, path: &str) {
+        match File::create(path).and_then(|mut f| f.write_all(&chip8.save_state(keypad))) {
+            Ok(()) => println!("Saved state to {}", path),
+            Err(e) => eprintln!("Error while writing {}: {}", path, e),
         }
     }
+
+    //the held keys of the state are dropped, the keyboard reports the real ones
+    fn load_slot(chip8: &mut Chip8, path: &str) {
+        match load_state_file(chip8, path) {
+            Ok(_) => println!("Loaded state from {}", path),
+            Err(e) => eprintln!("Error while loading {}: {}", path, e),
+        }
+    }
+}
+
+#[cfg(not(feature = "sdl"))]
+mod window {
+    use headless;
+    use options::Options;
+    use recorder::Recorder;
+
+    use rip8::{Chip8, Keypad};
+
+    pub fn run(_chip8: &mut Chip8, _keypad: Keypad, _recorder: &mut Recorder, _options: &Options) -> i32 {
+        eprintln!("rip8 was built without the sdl feature, only --headless is available");
+        headless::EXIT_USAGE
+    }
 }
@@ -0,0 +1,184 @@
+extern crate sdl2;
+
+use display::{draw_text, CELL_HEIGHT, CELL_WIDTH, HIGHLIGHT, PANEL_BACKGROUND, TEXT};
+
+use rip8::Chip8;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+/// Bytes per row of the hex view.
+const BYTES_PER_ROW: usize = 16;
+/// Rows of the hex view.
+const ROWS: usize = 32;
+/// Rows of the sprite view, the largest DXYN sprite.
+const SPRITE_ROWS: usize = 16;
+/// Size of a sprite pixel.
+const SPRITE_SCALE: u32 = 12;
+/// Frames a written byte stays highlighted, fading out.
+const FADE_FRAMES: u8 = 60;
+/// Columns of text left of the sprite view: address, 16 bytes and a margin.
+const HEX_COLUMNS: u32 = 5 + 3 * BYTES_PER_ROW as u32 + 2;
+
+const PC_BACKGROUND: Color = Color { r: 0x00, g: 0x60, b: 0x00, a: 0xFF };
+const INDEX_BACKGROUND: Color = Color { r: 0x00, g: 0x40, b: 0x80, a: 0xFF };
+const SPRITE_BACKGROUND: Color = Color { r: 0x50, g: 0x20, b: 0x60, a: 0xFF };
+const WRITTEN: Color = Color { r: 0xFF, g: 0x40, b: 0x40, a: 0xFF };
+
+/// Second window with a hex view of the whole memory and a sprite view.
+///
+/// The hex view marks the bytes at pc and I and fades out recently written
+/// bytes. The sprite view draws the bytes at I, or at the byte clicked in
+/// the hex view, as 8 pixel wide rows like DXYN.
+pub struct MemoryViewer {
+    canvas: Canvas<Window>,
+    //first row of the hex view
+    top: usize,
+    //address of the sprite view, None follows I
+    sprite: Option<usize>,
+    //memory of the last frame and frames since each byte was last written
+    previous: Vec<u8>,
+    ages: Vec<u8>,
+}
+
+impl MemoryViewer {
+    pub fn new(sdl_context: &sdl2::Sdl, chip8: &Chip8) -> Result<MemoryViewer, String> {
+        let width = (HEX_COLUMNS + 2) * CELL_WIDTH + 8 * SPRITE_SCALE;
+        let height = (ROWS as u32 + 5) * CELL_HEIGHT;
+        let window = sdl_context.video()?
+            .window("Rip-8 memory", width, height)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        let memory = chip8.memory();
+        Ok(MemoryViewer {
+            canvas,
+            top: chip8.pc() as usize / BYTES_PER_ROW,
+            sprite: None,
+            previous: memory.to_vec(),
+            ages: vec![FADE_FRAMES; memory.len()],
+        })
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    /// Finds the bytes written since the last call, meant to be called once per frame.
+    pub fn update(&mut self, memory: &[u8]) {
+        if memory.len() != self.previous.len() {
+            //a state of another mode was loaded
+            self.previous = memory.to_vec();
+            self.ages = vec![FADE_FRAMES; memory.len()];
+        }
+        for ((age, previous), &byte) in self.ages.iter_mut().zip(self.previous.iter_mut()).zip(memory.iter()) {
+            if *previous != byte {
+                *previous = byte;
+                *age = 0;
+            } else if *age < FADE_FRAMES {
+                *age += 1;
+            }
+        }
+    }
+
+    /// Moves the hex view by `rows`.
+    pub fn scroll(&mut self, rows: i32) {
+        let last = (self.previous.len() / BYTES_PER_ROW).saturating_sub(ROWS);
+        self.top = (self.top as i64 + rows as i64).max(0).min(last as i64) as usize;
+    }
+
+    /// Moves the sprite view by `bytes`, it stops following I.
+    pub fn move_sprite(&mut self, bytes: i32, index: u16) {
+        let address = self.sprite.unwrap_or(index as usize) as i64 + bytes as i64;
+        self.sprite = Some(address.max(0).min(self.previous.len() as i64 - 1) as usize);
+    }
+
+    /// Lets the sprite view follow I again.
+    pub fn follow_index(&mut self) {
+        self.sprite = None;
+    }
+
+    /// Shows the sprite at the byte under the mouse, if any.
+    pub fn click(&mut self, x: i32, y: i32) {
+        let (column, row) = (x / CELL_WIDTH as i32, y / CELL_HEIGHT as i32 - 2);
+        //bytes start after the address and take two columns plus a space
+        let byte = (column - 6) / 3;
+        if row < 0 || row >= ROWS as i32 || column < 6 || (column - 6) % 3 == 2 || byte >= BYTES_PER_ROW as i32 {
+            return;
+        }
+        let address = (self.top + row as usize) * BYTES_PER_ROW + byte as usize;
+        if address < self.previous.len() {
+            self.sprite = Some(address);
+        }
+    }
+
+    pub fn render(&mut self, chip8: &Chip8) {
+        let memory = chip8.memory();
+        let (pc, index) = (chip8.pc() as usize, chip8.index() as usize);
+        let sprite = self.sprite.unwrap_or(index);
+        let (cell_width, cell_height) = (CELL_WIDTH as i32, CELL_HEIGHT as i32);
+
+        self.canvas.set_draw_color(PANEL_BACKGROUND);
+        self.canvas.clear();
+        let start = self.top * BYTES_PER_ROW;
+        let end = (start + ROWS * BYTES_PER_ROW).min(memory.len());
+        let header = format!("{:04X}-{:04X}  PC {:03X}  I {:03X}", start, end - 1, pc, index);
+        draw_text(&mut self.canvas, cell_width, cell_height / 2, &header, TEXT);
+
+        for (row, line) in (start..end).step_by(BYTES_PER_ROW).enumerate() {
+            let y = (row as i32 + 2) * cell_height;
+            draw_text(&mut self.canvas, cell_width, y, &format!("{:04X}", line), TEXT);
+            let row_end = (line + BYTES_PER_ROW).min(end);
+            for (offset, (&byte, &age)) in memory[line..row_end].iter().zip(self.ages[line..row_end].iter()).enumerate() {
+                let address = line + offset;
+                let x = (6 + 3 * offset as i32) * cell_width;
+                let background = if address == pc || address == pc + 1 {
+                    Some(PC_BACKGROUND)
+                } else if address == index {
+                    Some(INDEX_BACKGROUND)
+                } else if address >= sprite && address < sprite + SPRITE_ROWS {
+                    Some(SPRITE_BACKGROUND)
+                } else {
+                    None
+                };
+                if let Some(background) = background {
+                    self.canvas.set_draw_color(background);
+                    let _ = self.canvas.fill_rect(Rect::new(x - 2, y - 2, 2 * CELL_WIDTH + 4, CELL_HEIGHT));
+                }
+                draw_text(&mut self.canvas, x, y, &format!("{:02X}", byte), fade(WRITTEN, TEXT, age));
+            }
+        }
+
+        //the sprite view right of the hex view, its bytes are marked in the hex view
+        let left = HEX_COLUMNS as i32 * cell_width;
+        draw_text(&mut self.canvas, left, cell_height / 2, &format!("SPRITE {:03X}", sprite), HIGHLIGHT);
+        let top = 2 * cell_height;
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        let _ = self.canvas.fill_rect(Rect::new(left, top, 8 * SPRITE_SCALE, SPRITE_ROWS as u32 * SPRITE_SCALE));
+        let mut pixels = Vec::new();
+        for row in 0..SPRITE_ROWS {
+            let byte = memory.get(sprite + row).cloned().unwrap_or(0);
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    pixels.push(Rect::new(left + bit * SPRITE_SCALE as i32, top + (row as u32 * SPRITE_SCALE) as i32, SPRITE_SCALE, SPRITE_SCALE));
+                }
+            }
+        }
+        self.canvas.set_draw_color(Color::RGB(0xFF, 0xFF, 0xFF));
+        let _ = self.canvas.fill_rects(&pixels);
+
+        let help = ["UP/DOWN PGUP/PGDN: SCROLL  HOME: SPRITE AT I", "CLICK A BYTE OR LEFT/RIGHT: MOVE THE SPRITE"];
+        for (i, line) in help.iter().enumerate() {
+            draw_text(&mut self.canvas, cell_width, (ROWS as i32 + 2 + i as i32) * cell_height + cell_height / 2, line, TEXT);
+        }
+        self.canvas.present();
+    }
+}
+
+//blends from `from` to `to` over FADE_FRAMES frames
+fn fade(from: Color, to: Color, age: u8) -> Color {
+    let blend = |a: u8, b: u8| (a as i32 + (b as i32 - a as i32) * age as i32 / FADE_FRAMES as i32) as u8;
+    Color::RGB(blend(from.r, to.r), blend(from.g, to.g), blend(from.b, to.b))
+}
@@ -0,0 +1,178 @@
+use chip8::Mode;
+use keypad::Keypad;
+use quirks::{LoadStore, Quirks};
+use state::{StateError, StateReader, StateWriter};
+
+use alloc::vec::Vec;
+
+/// First bytes of every movie.
+pub const MOVIE_MAGIC: [u8; 4] = *b"R8MV";
+/// Version of the movie layout, bumped whenever the layout changes.
+pub const MOVIE_VERSION: u16 = 1;
+
+/// Keypad state of every frame of a session, replayable from power-on.
+///
+/// The header holds everything else a replay depends on, so the same ROM
+/// reaches the same state on every replay.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    /// `rom_hash` of the recorded ROM.
+    pub rom_hash: u64,
+    pub mode: Mode,
+    pub quirks: Quirks,
+    /// Seed of the `Xorshift` generator of CXNN.
+    pub seed: u32,
+    pub cycles_per_frame: u32,
+    /// Keys held during each frame.
+    pub frames: Vec<Keypad>,
+}
+
+impl Movie {
+    /// Starts an empty recording.
+    pub fn new(rom: &[u8], mode: Mode, quirks: Quirks, seed: u32, cycles_per_frame: u32) -> Movie {
+        Movie {
+            rom_hash: rom_hash(rom),
+            mode,
+            quirks,
+            seed,
+            cycles_per_frame,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Appends the keys held during the next frame.
+    pub fn record(&mut self, keypad: &Keypad) {
+        self.frames.push(*keypad);
+    }
+
+    /// Returns the keys held during `frame`, None after the end of the movie.
+    pub fn keypad(&self, frame: u64) -> Option<Keypad> {
+        self.frames.get(frame as usize).cloned()
+    }
+
+    /// Number of recorded frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::with_header(MOVIE_MAGIC, MOVIE_VERSION);
+        writer.u64(self.rom_hash);
+        writer.u8(match self.mode {
+            Mode::Chip8 => 0,
+            Mode::SuperChip => 1,
+            Mode::XoChip => 2,
+        });
+        writer.bool(self.quirks.shift_vx);
+        writer.u8(match self.quirks.load_store {
+            LoadStore::IncrementXPlusOne => 0,
+            LoadStore::IncrementX => 1,
+            LoadStore::Unchanged => 2,
+        });
+        writer.bool(self.quirks.jump_vx);
+        writer.bool(self.quirks.vf_reset);
+        writer.bool(self.quirks.wrap_sprites);
+        writer.bool(self.quirks.display_wait);
+        writer.u32(self.seed);
+        writer.u32(self.cycles_per_frame);
+        writer.u32(self.frames.len() as u32);
+        for keypad in self.frames.iter() {
+            writer.u16(keypad.bits());
+        }
+        writer.data
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Movie, StateError> {
+        let mut reader = StateReader::with_header(data, MOVIE_MAGIC, MOVIE_VERSION)?;
+        let rom_hash = reader.u64()?;
+        let mode = match reader.u8()? {
+            0 => Mode::Chip8,
+            1 => Mode::SuperChip,
+            2 => Mode::XoChip,
+            _ => return Err(StateError::InvalidField("mode")),
+        };
+        let shift_vx = reader.bool("shift quirk")?;
+        let load_store = match reader.u8()? {
+            0 => LoadStore::IncrementXPlusOne,
+            1 => LoadStore::IncrementX,
+            2 => LoadStore::Unchanged,
+            _ => return Err(StateError::InvalidField("load/store quirk")),
+        };
+        let quirks = Quirks {
+            shift_vx,
+            load_store,
+            jump_vx: reader.bool("jump quirk")?,
+            vf_reset: reader.bool("VF reset quirk")?,
+            wrap_sprites: reader.bool("wrap quirk")?,
+            display_wait: reader.bool("display wait quirk")?,
+        };
+        let seed = reader.u32()?;
+        let cycles_per_frame = reader.u32()?;
+        let len = reader.u32()? as usize;
+        let mut frames = Vec::new();
+        for _ in 0..len {
+            frames.push(Keypad::from_bits(reader.u16()?));
+        }
+        reader.finish()?;
+        Ok(Movie { rom_hash, mode, quirks, seed, cycles_per_frame, frames })
+    }
+}
+
+/// 64 bit FNV-1a hash identifying a ROM.
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::Chip8;
+    use rng::Xorshift;
+
+    //V2 counts the cycles key 5 is held, V3 is random
+    const ROM: [u8; 12] = [0x60, 0x05, 0xE0, 0x9E, 0x12, 0x0A, 0x72, 0x01, 0xC3, 0xFF, 0x12, 0x02];
+
+    //runs every frame of `movie` on a fresh machine set up from its header
+    fn play(movie: &Movie) -> Chip8 {
+        let mut chip8 = Chip8::with_mode(ROM.to_vec(), movie.mode).unwrap();
+        chip8.quirks = movie.quirks;
+        chip8.set_rng(Box::new(Xorshift::new(movie.seed)));
+        for frame in 0..movie.len() as u64 {
+            chip8.run_frame(&movie.keypad(frame).unwrap(), movie.cycles_per_frame).unwrap();
+        }
+        chip8
+    }
+
+    #[test]
+    fn replay_reaches_the_recorded_state() {
+        let mut movie = Movie::new(&ROM, Mode::SuperChip, Quirks::schip(), 1234, 9);
+        let mut keypad = Keypad::new();
+        for frame in 0..30 {
+            keypad.set(5, frame % 7 < 3);
+            movie.record(&keypad);
+        }
+        let recorded = play(&movie);
+        assert!(recorded.registers()[2] > 0);
+
+        let replayed = Movie::parse(&movie.to_bytes()).unwrap();
+        assert_eq!(replayed, movie);
+        let replay = play(&replayed);
+        assert_eq!(replay.save_state(&keypad), recorded.save_state(&keypad));
+    }
+
+    #[test]
+    fn invalid_movies_are_rejected() {
+        let data = Movie::new(&ROM, Mode::Chip8, Quirks::vip(), 1, 10).to_bytes();
+        assert_eq!(Movie::parse(b"R8ST"), Err(StateError::BadMagic));
+        assert_eq!(Movie::parse(&data[..data.len() - 1]), Err(StateError::Truncated));
+        let mut mode = data.clone();
+        mode[14] = 3;
+        assert_eq!(Movie::parse(&mode), Err(StateError::InvalidField("mode")));
+    }
+}
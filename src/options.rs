@@ -0,0 +1,320 @@
+use rip8::{ErrorMode, Mode, Quirks, Tone, TraceFormat, Tracing, Waveform};
+
+use std::fmt;
+
+/// Colours of the four pixel values (0xRRGGBB): background, plane 1, plane 2, both planes.
+pub const DEFAULT_PALETTE: [u32; 4] = [0x000000, 0xFFFFFF, 0xFF6600, 0x662200];
+
+/// Seconds of states kept for rewinding if nothing else is requested.
+pub const DEFAULT_REWIND_SECONDS: u32 = 30;
+
+/// Sample rate requested from the audio device.
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Instructions executed per 60 Hz frame if nothing else is requested.
+pub const DEFAULT_CYCLES_PER_FRAME: u32 = 10;
+
+/// Messages printed if nothing else is requested, failing instructions and worse.
+pub const DEFAULT_LOG_LEVELS: &str = "warn";
+
+pub const USAGE: &str = "\
+Usage: rip8 [OPTIONS] <ROM>
+       rip8 debug [OPTIONS] <ROM>
+       rip8 dap [--port <PORT>] [OPTIONS] [<ROM>]
+       rip8 asm <SOURCE> [-o <FILE>] [--symbols <FILE>]
+       rip8 disasm [--mode <MODE>] [-o <FILE>] <ROM>
+       rip8 trace-diff [--no-screen] <TRACE> <OTHER>
+
+Options:
+    --ips <N>    instructions per second (rounded to a multiple of 60)
+    --ipf <N>    instructions per 60 Hz frame (default: 10)
+    --palette <RRGGBB,RRGGBB,RRGGBB,RRGGBB>
+                 colours of the background, plane 1, plane 2 and both planes
+    --waveform <square|triangle|sawtooth|sine>
+                 buzzer waveform (default: square)
+    --frequency <HZ>
+                 buzzer frequency, below 22050 (default: 440)
+    --volume <0.0-1.0>
+                 buzzer volume (default: 0.25)
+    --mute       disable audio output
+    --mode <chip8|schip|xochip>
+                 instruction set (default: chip8)
+    --quirks <vip|chip48|schip|xochip>
+                 compatibility quirks preset (default: matches the mode)
+    --strict     stop at the first error instead of skipping the faulting instruction
+    --seed <N>   seed of the CXNN random numbers (default: based on the time)
+    --record <FILE>
+                 record the keypad state of every frame into a movie
+    --replay <FILE>
+                 replay a movie, its mode, quirks, seed and speed override the options
+    --load-state <FILE>
+                 start from a save state instead of booting the ROM
+    --rewind-seconds <N>
+                 seconds kept for rewinding with Backspace (default: 30, 0 disables)
+    --log <LEVELS>
+                 messages printed to stderr, a level of error, warn, info, debug
+                 or off, for all targets or per target like 'warn,input=debug'
+                 (targets: cpu, display, input, audio, machine-code; default: warn)
+    --trace <FILE>
+                 write pc, opcode, registers, I, timers, a screen hash and the
+                 disassembly of every executed instruction to FILE
+    --trace-format <text|binary>
+                 format of the trace, binary is compact (default: text)
+    --trace-range <START-END>
+                 only trace instructions at the hex addresses START to END
+    --trace-cycles <FROM-TO>
+                 only trace the cycles FROM to TO, counted from 1
+
+Save states: F1-F4 save to slot 1-4 (<ROM>.state1 ...), Shift+F1-F4 load them.
+F5 shows or hides the debug panel, F6 opens the memory and sprite viewer.
+
+Headless mode:
+    --headless   run without a window, print the final registers to stdout
+    --frames <N> number of 60 Hz frames to run (default: 600)
+    --cycles <N> number of instructions to run, overrides --frames
+    --input <FILE>
+                 scripted keypad input, lines of '<frame> <key> <down|up>'
+    --screenshot <FILE>
+                 write the final framebuffer, as PBM if FILE ends with .pbm,
+                 as ASCII art otherwise
+    -h, --help   print this help";
+
+/// Command line options of the rip8 binary.
+///
+/// The window options are still parsed without the `sdl` feature.
+#[derive(Debug)]
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+pub struct Options {
+    pub rom: String,
+    pub cycles_per_frame: u32,
+    pub tone: Tone,
+    pub mute: bool,
+    pub error_mode: ErrorMode,
+    pub quirks: Quirks,
+    pub mode: Mode,
+    pub palette: [u32; 4],
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub cycles: Option<u64>,
+    pub input_script: Option<String>,
+    pub screenshot: Option<String>,
+    pub load_state: Option<String>,
+    pub rewind_seconds: u32,
+    pub seed: Option<u32>,
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    /// Log levels and step filters, steps are recorded if `trace` is set.
+    pub tracing: Tracing,
+    pub trace: Option<String>,
+    pub trace_format: TraceFormat,
+}
+
+#[derive(Debug)]
+pub enum OptionsError {
+    Help,
+    MissingRom,
+    MissingValue(String),
+    InvalidValue(String, String),
+    UnknownOption(String),
+}
+
+impl fmt::Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OptionsError::Help => write!(f, "{}", USAGE),
+            OptionsError::MissingRom => write!(f, "no ROM given\n\n{}", USAGE),
+            OptionsError::MissingValue(ref option) => write!(f, "missing value for {}", option),
+            OptionsError::InvalidValue(ref option, ref value) =>
+                write!(f, "invalid value '{}' for {}", value, option),
+            OptionsError::UnknownOption(ref option) => write!(f, "unknown option {}\n\n{}", option, USAGE),
+        }
+    }
+}
+
+impl Options {
+    /// Parses the command line arguments, without the program name.
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, OptionsError> {
+        let mut rom = None;
+        let mut cycles_per_frame = DEFAULT_CYCLES_PER_FRAME;
+        let mut tone = Tone::default();
+        let mut mute = false;
+        let mut error_mode = ErrorMode::Lenient;
+        let mut quirks = None;
+        let mut mode = Mode::Chip8;
+        let mut palette = DEFAULT_PALETTE;
+        let mut headless = false;
+        let mut frames = None;
+        let mut cycles = None;
+        let mut input_script = None;
+        let mut screenshot = None;
+        let mut load_state = None;
+        let mut rewind_seconds = DEFAULT_REWIND_SECONDS;
+        let mut seed = None;
+        let mut record = None;
+        let mut replay = None;
+        let mut tracing = Tracing::new();
+        tracing.set_levels(DEFAULT_LOG_LEVELS).expect("valid default log levels");
+        let mut trace = None;
+        let mut trace_format = TraceFormat::Text;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Err(OptionsError::Help),
+                "--ips" => {
+                    let ips: u32 = parse_value(&arg, args.next())?;
+                    cycles_per_frame = (ips / 60).max(1);
+                }
+                "--ipf" => {
+                    cycles_per_frame = parse_value(&arg, args.next())?;
+                    if cycles_per_frame == 0 {
+                        return Err(OptionsError::InvalidValue(arg, String::from("0")));
+                    }
+                }
+                "--waveform" => {
+                    tone.waveform = parse_value::<Waveform>(&arg, args.next())?;
+                }
+                "--frequency" => {
+                    let frequency: f32 = parse_value(&arg, args.next())?;
+                    //higher frequencies can not be played at the sample rate
+                    if !frequency.is_finite() || frequency <= 0.0 || frequency >= SAMPLE_RATE as f32 / 2.0 {
+                        return Err(OptionsError::InvalidValue(arg, frequency.to_string()));
+                    }
+                    tone.frequency = frequency;
+                }
+                "--volume" => {
+                    let volume: f32 = parse_value(&arg, args.next())?;
+                    if !(0.0..=1.0).contains(&volume) {
+                        return Err(OptionsError::InvalidValue(arg, volume.to_string()));
+                    }
+                    tone.volume = volume;
+                }
+                "--mute" => mute = true,
+                "--strict" => error_mode = ErrorMode::Strict,
+                "--quirks" => {
+                    quirks = Some(parse_value(&arg, args.next())?);
+                }
+                "--palette" => {
+                    let value = args.next().ok_or_else(|| OptionsError::MissingValue(arg.clone()))?;
+                    palette = parse_palette(&value)
+                        .ok_or_else(|| OptionsError::InvalidValue(arg.clone(), value.clone()))?;
+                }
+                "--mode" => {
+                    mode = parse_value(&arg, args.next())?;
+                }
+                "--headless" => headless = true,
+                "--frames" => {
+                    frames = Some(parse_value(&arg, args.next())?);
+                }
+                "--cycles" => {
+                    cycles = Some(parse_value(&arg, args.next())?);
+                }
+                "--input" => {
+                    input_script = Some(parse_value(&arg, args.next())?);
+                }
+                "--screenshot" => {
+                    screenshot = Some(parse_value(&arg, args.next())?);
+                }
+                "--load-state" => {
+                    load_state = Some(parse_value(&arg, args.next())?);
+                }
+                "--rewind-seconds" => {
+                    rewind_seconds = parse_value(&arg, args.next())?;
+                }
+                "--seed" => {
+                    seed = Some(parse_value(&arg, args.next())?);
+                }
+                "--record" => {
+                    record = Some(parse_value(&arg, args.next())?);
+                }
+                "--replay" => {
+                    replay = Some(parse_value(&arg, args.next())?);
+                }
+                "--log" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    tracing.set_levels(&value).map_err(|_| OptionsError::InvalidValue(arg.clone(), value.clone()))?;
+                }
+                "--trace" => {
+                    trace = Some(parse_value(&arg, args.next())?);
+                }
+                "--trace-format" => {
+                    trace_format = parse_value(&arg, args.next())?;
+                }
+                "--trace-range" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    let (start, end) = parse_range(&value, 16)
+                        .ok_or_else(|| OptionsError::InvalidValue(arg.clone(), value.clone()))?;
+                    tracing.addresses = Some(start as usize..end as usize + 1);
+                }
+                "--trace-cycles" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    let (from, to) = parse_range(&value, 10)
+                        .ok_or_else(|| OptionsError::InvalidValue(arg.clone(), value.clone()))?;
+                    tracing.cycles = Some(from..to + 1);
+                }
+                _ if arg.starts_with('-') => return Err(OptionsError::UnknownOption(arg)),
+                _ => rom = Some(arg),
+            }
+        }
+
+        tracing.steps = trace.is_some();
+        Ok(Options {
+            rom: rom.ok_or(OptionsError::MissingRom)?,
+            cycles_per_frame,
+            tone,
+            mute,
+            error_mode,
+            quirks: quirks.unwrap_or(match mode {
+                Mode::Chip8 => Quirks::vip(),
+                Mode::SuperChip => Quirks::schip(),
+                Mode::XoChip => Quirks::xochip(),
+            }),
+            mode,
+            palette,
+            headless,
+            frames,
+            cycles,
+            input_script,
+            screenshot,
+            load_state,
+            rewind_seconds,
+            seed,
+            record,
+            replay,
+            tracing,
+            trace,
+            trace_format,
+        })
+    }
+}
+
+fn parse_value<T: ::std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, OptionsError> {
+    let value = value.ok_or_else(|| OptionsError::MissingValue(option.to_string()))?;
+    value.parse().map_err(|_| OptionsError::InvalidValue(option.to_string(), value))
+}
+
+//parses an inclusive range like 200-2FF, the end must not be before the start
+fn parse_range(value: &str, radix: u32) -> Option<(u64, u64)> {
+    let dash = value.find('-')?;
+    let start = u64::from_str_radix(&value[..dash], radix).ok()?;
+    let end = u64::from_str_radix(&value[dash + 1..], radix).ok()?;
+    if end < start || end == u64::MAX {
+        return None;
+    }
+    Some((start, end))
+}
+
+fn parse_palette(value: &str) -> Option<[u32; 4]> {
+    let mut palette = [0; 4];
+    let mut colors = value.split(',');
+    for color in palette.iter_mut() {
+        let hex = colors.next()?.trim_start_matches('#');
+        if hex.len() != 6 {
+            return None;
+        }
+        *color = u32::from_str_radix(hex, 16).ok()?;
+    }
+    if colors.next().is_some() {
+        return None;
+    }
+    Some(palette)
+}
@@ -0,0 +1,165 @@
+use rip8::{Chip8, Input, Instruction, Keypad};
+
+use std::time::{Duration, Instant};
+
+/// Characters per line of the panel.
+pub const COLUMNS: usize = 32;
+
+/// 5x7 glyphs of the characters 0x20 to 0x5F, one byte per column, bit 0 is the top row.
+/// Lower case letters are drawn in upper case.
+const FONT: [[u8; 5]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], //space
+    [0x00, 0x00, 0x5F, 0x00, 0x00], //exclamation mark
+    [0x00, 0x07, 0x00, 0x07, 0x00], //"
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], //#
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], //$
+    [0x23, 0x13, 0x08, 0x64, 0x62], //%
+    [0x36, 0x49, 0x55, 0x22, 0x50], //&
+    [0x00, 0x05, 0x03, 0x00, 0x00], //'
+    [0x00, 0x1C, 0x22, 0x41, 0x00], //(
+    [0x00, 0x41, 0x22, 0x1C, 0x00], //)
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], //*
+    [0x08, 0x08, 0x3E, 0x08, 0x08], //+
+    [0x00, 0x50, 0x30, 0x00, 0x00], //,
+    [0x08, 0x08, 0x08, 0x08, 0x08], //-
+    [0x00, 0x60, 0x60, 0x00, 0x00], //.
+    [0x20, 0x10, 0x08, 0x04, 0x02], //slash
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], //0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], //1
+    [0x42, 0x61, 0x51, 0x49, 0x46], //2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], //3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], //4
+    [0x27, 0x45, 0x45, 0x45, 0x39], //5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], //6
+    [0x01, 0x71, 0x09, 0x05, 0x03], //7
+    [0x36, 0x49, 0x49, 0x49, 0x36], //8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], //9
+    [0x00, 0x36, 0x36, 0x00, 0x00], //:
+    [0x00, 0x56, 0x36, 0x00, 0x00], //;
+    [0x00, 0x08, 0x14, 0x22, 0x41], //<
+    [0x14, 0x14, 0x14, 0x14, 0x14], //=
+    [0x41, 0x22, 0x14, 0x08, 0x00], //>
+    [0x02, 0x01, 0x51, 0x09, 0x06], //?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], //@
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], //A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], //B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], //C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], //D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], //E
+    [0x7F, 0x09, 0x09, 0x01, 0x01], //F
+    [0x3E, 0x41, 0x41, 0x51, 0x32], //G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], //H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], //I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], //J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], //K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], //L
+    [0x7F, 0x02, 0x04, 0x02, 0x7F], //M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], //N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], //O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], //P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], //Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], //R
+    [0x46, 0x49, 0x49, 0x49, 0x31], //S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], //T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], //U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], //V
+    [0x7F, 0x20, 0x18, 0x20, 0x7F], //W
+    [0x63, 0x14, 0x08, 0x14, 0x63], //X
+    [0x03, 0x04, 0x78, 0x04, 0x03], //Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], //Z
+    [0x00, 0x00, 0x7F, 0x41, 0x41], //[
+    [0x02, 0x04, 0x08, 0x10, 0x20], //backslash
+    [0x41, 0x41, 0x7F, 0x00, 0x00], //]
+    [0x04, 0x02, 0x01, 0x02, 0x04], //^
+    [0x40, 0x40, 0x40, 0x40, 0x40], //_
+];
+
+/// Returns the glyph of `c`, unknown characters are drawn as `?`.
+pub fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '|' => [0x00, 0x00, 0x7F, 0x00, 0x00],
+        c if (' '..='_').contains(&c) => FONT[c as usize - 0x20],
+        _ => FONT['?' as usize - 0x20],
+    }
+}
+
+/// A line of the panel, made of normal and highlighted pieces of text.
+pub struct Line(pub Vec<(String, bool)>);
+
+impl Line {
+    fn plain(text: String) -> Line {
+        Line(vec![(text, false)])
+    }
+}
+
+/// Keys of the hex keypad as laid out on the COSMAC VIP.
+const KEYPAD_LAYOUT: [[u8; 4]; 4] = [[0x1, 0x2, 0x3, 0xC], [0x4, 0x5, 0x6, 0xD], [0x7, 0x8, 0x9, 0xE], [0xA, 0x0, 0xB, 0xF]];
+
+/// Builds `rows` lines showing the state of `chip8`.
+///
+/// The disassembly around pc fills the lines below the registers.
+pub fn lines(chip8: &Chip8, keypad: &Keypad, ips: u64, rows: usize) -> Vec<Line> {
+    let mut lines = vec![
+        Line::plain(format!("PC {:03X}  I {:03X}  SP {:X}", chip8.pc(), chip8.index(), chip8.stack().len())),
+        Line::plain(format!("DT {:02X}  ST {:02X}  IPS {}", chip8.delay_timer(), chip8.sound_timer(), ips)),
+    ];
+    //the registers with the keypad to the right, pressed keys highlighted
+    for (row, keys) in KEYPAD_LAYOUT.iter().enumerate() {
+        let registers: Vec<String> = (row * 4..row * 4 + 4)
+            .map(|x| format!("V{:X} {:02X}", x, chip8.registers()[x]))
+            .collect();
+        let mut line = vec![(format!("{} ", registers.join(" ")), false)];
+        for &key in keys.iter() {
+            line.push((String::from(" "), false));
+            line.push((format!("{:X}", key), keypad.is_pressed(key)));
+        }
+        lines.push(Line(line));
+    }
+    //as many return addresses as fit, the most recent ones
+    let stack: Vec<String> = chip8.stack().iter().rev().take((COLUMNS - 6) / 4).map(|address| format!("{:03X}", address)).collect();
+    lines.push(Line::plain(format!("STACK {}", stack.join(" "))));
+    lines.push(Line::plain(String::new()));
+
+    let listing = rows.saturating_sub(lines.len());
+    let mut address = (chip8.pc() as usize).saturating_sub(listing / 2 * 2);
+    for _ in 0..listing {
+        let instruction = match Instruction::decode_at(chip8.memory(), address) {
+            Some(instruction) => instruction,
+            None => break,
+        };
+        let current = address == chip8.pc() as usize;
+        let mut text = format!("{:03X} {}", address, instruction);
+        text.truncate(COLUMNS);
+        lines.push(Line(vec![(text, current)]));
+        address += instruction.size();
+    }
+    lines
+}
+
+/// Measures the emulated instructions per second over one second intervals.
+pub struct IpsCounter {
+    cycles: u64,
+    since: Instant,
+    pub ips: u64,
+}
+
+impl IpsCounter {
+    pub fn new() -> IpsCounter {
+        IpsCounter {
+            cycles: 0,
+            since: Instant::now(),
+            ips: 0,
+        }
+    }
+
+    /// Counts `cycles` executed instructions.
+    pub fn add(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        let elapsed = self.since.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.ips = (self.cycles as f64 / elapsed.as_secs_f64()).round() as u64;
+            self.cycles = 0;
+            self.since = Instant::now();
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use alloc::string::String;
+use core::str::FromStr;
+
+/// How FX55/FX65 change I after the transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadStore {
+    /// I is incremented by X + 1 (COSMAC VIP).
+    IncrementXPlusOne,
+    /// I is incremented by X (CHIP-48).
+    IncrementX,
+    /// I is left unchanged (SUPER-CHIP).
+    Unchanged,
+}
+
+/// Instruction behaviours that differ between CHIP-8 implementations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift V[X] in place instead of V[Y].
+    pub shift_vx: bool,
+    /// Effect of FX55/FX65 on I.
+    pub load_store: LoadStore,
+    /// BNNN is BXNN and jumps to XNN + V[X] instead of NNN + V[0].
+    pub jump_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset V[F] to 0.
+    pub vf_reset: bool,
+    /// Sprites wrap around the screen edges instead of being clipped.
+    pub wrap_sprites: bool,
+    /// DXYN waits for the next 60 Hz frame before drawing.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original interpreter of the COSMAC VIP.
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_vx: false,
+            load_store: LoadStore::IncrementXPlusOne,
+            jump_vx: false,
+            vf_reset: true,
+            wrap_sprites: false,
+            display_wait: true,
+        }
+    }
+
+    /// CHIP-48 for the HP-48 calculators.
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_vx: true,
+            load_store: LoadStore::IncrementX,
+            jump_vx: true,
+            vf_reset: false,
+            wrap_sprites: false,
+            display_wait: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1.
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift_vx: true,
+            load_store: LoadStore::Unchanged,
+            jump_vx: true,
+            vf_reset: false,
+            wrap_sprites: false,
+            display_wait: false,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_vx: false,
+            load_store: LoadStore::IncrementXPlusOne,
+            jump_vx: false,
+            vf_reset: false,
+            wrap_sprites: true,
+            display_wait: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::vip()
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    /// Parses the name of a preset.
+    fn from_str(s: &str) -> Result<Quirks, String> {
+        match s {
+            "vip" | "chip8" => Ok(Quirks::vip()),
+            "chip48" => Ok(Quirks::chip48()),
+            "schip" | "superchip" => Ok(Quirks::schip()),
+            "xochip" => Ok(Quirks::xochip()),
+            _ => Err(format!("unknown quirks preset '{}'", s)),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use options::Options;
+
+use rip8::{rom_hash, Keypad, Movie};
+
+use std::fs::File;
+use std::io::prelude::*;
+
+/// Reads the `--replay` movie, if any, and makes `options` match its header.
+///
+/// Fails if the movie cannot be read or was recorded with another ROM.
+pub fn read_replay(options: &mut Options, rom: &[u8]) -> Result<Option<Movie>, String> {
+    let path = match options.replay {
+        Some(ref path) => path.clone(),
+        None => return Ok(None),
+    };
+    let mut data = Vec::new();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_end(&mut data))
+        .map_err(|e| format!("Error while reading {}: {}", path, e))?;
+    let movie = Movie::parse(&data).map_err(|e| format!("Error while reading {}: {}", path, e))?;
+    if movie.rom_hash != rom_hash(rom) {
+        return Err(format!("{} was recorded with a different ROM", path));
+    }
+    options.mode = movie.mode;
+    options.quirks = movie.quirks;
+    options.seed = Some(movie.seed);
+    options.cycles_per_frame = movie.cycles_per_frame;
+    Ok(Some(movie))
+}
+
+/// Replays and records the keypad state of a session, frame by frame.
+///
+/// While the replayed movie lasts its keys replace the live ones, after that
+/// the live keys take over. Recording captures whatever was passed to the machine.
+pub struct Recorder {
+    replay: Option<Movie>,
+    recording: Option<(Movie, String)>,
+    frame: u64,
+}
+
+impl Recorder {
+    /// `options` must already hold the final seed.
+    pub fn new(replay: Option<Movie>, options: &Options, rom: &[u8]) -> Recorder {
+        let recording = options.record.as_ref().map(|path| {
+            let seed = options.seed.expect("seed is chosen before recording");
+            let movie = Movie::new(rom, options.mode, options.quirks, seed, options.cycles_per_frame);
+            (movie, path.clone())
+        });
+        Recorder { replay, recording, frame: 0 }
+    }
+
+    /// Returns the keys for the next frame and records them.
+    pub fn next_frame(&mut self, live: &Keypad) -> Keypad {
+        let keypad = self.replay.as_ref()
+            .and_then(|movie| movie.keypad(self.frame))
+            .unwrap_or(*live);
+        if let Some((ref mut movie, _)) = self.recording {
+            movie.record(&keypad);
+        }
+        self.frame += 1;
+        keypad
+    }
+
+    /// Goes back one frame after the machine was rewound, dropping its recorded keys.
+    #[cfg(feature = "sdl")]
+    pub fn step_back(&mut self) {
+        self.frame = self.frame.saturating_sub(1);
+        if let Some((ref mut movie, _)) = self.recording {
+            movie.frames.truncate(self.frame as usize);
+        }
+    }
+
+    /// Number of frames in the replayed movie, if any.
+    pub fn replay_len(&self) -> Option<u64> {
+        self.replay.as_ref().map(|movie| movie.len() as u64)
+    }
+
+    /// True while a movie is replayed or recorded, loading a state would break it.
+    #[cfg(feature = "sdl")]
+    pub fn is_active(&self) -> bool {
+        self.replay.is_some() || self.recording.is_some()
+    }
+
+    /// Writes the recorded movie, if any.
+    pub fn save(&self) -> Result<(), String> {
+        if let Some((ref movie, ref path)) = self.recording {
+            File::create(path)
+                .and_then(|mut f| f.write_all(&movie.to_bytes()))
+                .map_err(|e| format!("Error while writing {}: {}", path, e))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use chip8::Chip8;
+
+use alloc::collections::VecDeque;
+use core::mem;
+
+/// Ring buffer of the most recent machine states, one per frame.
+///
+/// Once full, pushing a state drops the oldest one. Every state is a full
+/// copy of the machine, about 12 KiB or 72 KiB in XO-CHIP mode.
+pub struct Rewind {
+    states: VecDeque<Chip8>,
+    capacity: usize,
+}
+
+impl Rewind {
+    /// Creates a buffer holding at most `capacity` states.
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Stores a snapshot of `chip8`.
+    pub fn push(&mut self, chip8: &Chip8) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(chip8.clone());
+    }
+
+    /// Restores the most recent snapshot into `chip8` and removes it.
+    ///
+    /// Returns false once there is nothing left to rewind. The breakpoints and
+    /// the tracing of `chip8` are kept.
+    pub fn step_back(&mut self, chip8: &mut Chip8) -> bool {
+        match self.states.pop_back() {
+            Some(mut state) => {
+                mem::swap(&mut state.breakpoints, &mut chip8.breakpoints);
+                mem::swap(&mut state.tracing, &mut chip8.tracing);
+                *chip8 = state;
+                chip8.draw_flag = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of stored states.
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+}
@@ -0,0 +1,148 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// Seed of the generator of a new `Chip8`, use `Chip8::set_rng` for another one.
+pub const DEFAULT_SEED: u32 = 0x2545_F491;
+
+/// Source of the random bytes of CXNN.
+///
+/// The generator is part of the machine state, so it has to be cloneable and
+/// its whole state has to fit into a `u64` for save states.
+pub trait Rng {
+    /// Returns the next random byte.
+    fn next_byte(&mut self) -> u8;
+    /// Returns the current state, as restored by `set_state`.
+    fn state(&self) -> u64;
+    /// Restores a state returned by `state`, returns false if it is invalid.
+    fn set_state(&mut self, state: u64) -> bool;
+    /// Identifies the kind of generator in save states, a state only loads into the same kind.
+    fn tag(&self) -> [u8; 4];
+    fn box_clone(&self) -> Box<dyn Rng>;
+}
+
+impl Clone for Box<dyn Rng> {
+    fn clone(&self) -> Box<dyn Rng> {
+        self.box_clone()
+    }
+}
+
+/// 32 bit xorshift generator, the default RNG.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Xorshift {
+    state: u32,
+}
+
+impl Xorshift {
+    /// Creates a generator from `seed`, every seed is valid.
+    ///
+    /// The seed is scrambled first, xorshift would start with the same bytes for small seeds.
+    pub fn new(seed: u32) -> Xorshift {
+        let state = scramble(seed);
+        Xorshift { state: if state == 0 { DEFAULT_SEED } else { state } }
+    }
+}
+
+//splitmix32: the golden ratio increment followed by the murmur3 finalizer
+fn scramble(seed: u32) -> u32 {
+    let mut z = seed.wrapping_add(0x9E37_79B9);
+    z = (z ^ (z >> 16)).wrapping_mul(0x85EB_CA6B);
+    z = (z ^ (z >> 13)).wrapping_mul(0xC2B2_AE35);
+    z ^ (z >> 16)
+}
+
+impl Rng for Xorshift {
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 24) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state as u64
+    }
+
+    fn set_state(&mut self, state: u64) -> bool {
+        //the generator gets stuck at zero
+        if state == 0 || state > u32::MAX as u64 {
+            return false;
+        }
+        self.state = state as u32;
+        true
+    }
+
+    fn tag(&self) -> [u8; 4] {
+        *b"XS32"
+    }
+
+    fn box_clone(&self) -> Box<dyn Rng> {
+        Box::new(*self)
+    }
+}
+
+/// Returns a fixed sequence of bytes over and over, e.g. to test games with known values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequenceRng {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl SequenceRng {
+    /// Creates a generator repeating `bytes`, which must not be empty.
+    pub fn new(bytes: Vec<u8>) -> SequenceRng {
+        assert!(!bytes.is_empty(), "SequenceRng needs at least one byte");
+        SequenceRng { bytes, position: 0 }
+    }
+}
+
+impl Rng for SequenceRng {
+    fn next_byte(&mut self) -> u8 {
+        let byte = self.bytes[self.position];
+        self.position = (self.position + 1) % self.bytes.len();
+        byte
+    }
+
+    fn state(&self) -> u64 {
+        self.position as u64
+    }
+
+    fn set_state(&mut self, state: u64) -> bool {
+        if state >= self.bytes.len() as u64 {
+            return false;
+        }
+        self.position = state as usize;
+        true
+    }
+
+    fn tag(&self) -> [u8; 4] {
+        *b"SEQ1"
+    }
+
+    fn box_clone(&self) -> Box<dyn Rng> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_seeds_give_different_first_bytes() {
+        let first: Vec<u8> = (0..16).map(|seed| Xorshift::new(seed).next_byte()).collect();
+        assert!(first[1] != first[2] && first[2] != first[3] && first[1] != first[3], "{:?}", first);
+        //unscrambled seeds all started with 0
+        let mut distinct = first.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert!(distinct.len() >= 12, "first bytes of seeds 0-15: {:?}", first);
+    }
+
+    #[test]
+    fn same_seed_gives_same_bytes() {
+        let (mut a, mut b) = (Xorshift::new(42), Xorshift::new(42));
+        for _ in 0..100 {
+            assert_eq!(a.next_byte(), b.next_byte());
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use keypad::Keypad;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Scripted keypad input for runs without a keyboard.
+///
+/// Every non-empty line of a script is `<frame> <key> <down|up>`, with the
+/// frame in decimal and the key as a hex digit. Lines starting with `#` are
+/// comments. Events are applied at the start of their frame:
+///
+/// ```text
+/// # start the game, then hold 4 for one second
+/// 30 F down
+/// 31 F up
+/// 60 4 down
+/// 120 4 up
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputScript {
+    //sorted by frame
+    events: Vec<(u64, u8, bool)>,
+}
+
+/// A line of an input script that could not be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub text: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid input script line {}: '{}'", self.line, self.text)
+    }
+}
+
+impl InputScript {
+    pub fn parse(script: &str) -> Result<InputScript, ScriptError> {
+        let mut events = Vec::new();
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || ScriptError { line: i + 1, text: line.to_string() };
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 3 {
+                return Err(error());
+            }
+            let frame = fields[0].parse().map_err(|_| error())?;
+            let key = u8::from_str_radix(fields[1], 16).ok().filter(|&key| key < 16).ok_or_else(error)?;
+            let pressed = match fields[2] {
+                "down" => true,
+                "up" => false,
+                _ => return Err(error()),
+            };
+            events.push((frame, key, pressed));
+        }
+        //stable, so events of the same frame keep their order
+        events.sort_by_key(|&(frame, _, _)| frame);
+        Ok(InputScript { events })
+    }
+
+    /// Applies all events of `frame` to `keypad`.
+    pub fn apply(&self, frame: u64, keypad: &mut Keypad) {
+        for &(_, key, pressed) in self.events.iter().filter(|&&(f, _, _)| f == frame) {
+            keypad.set(key, pressed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use input::Input;
+
+    #[test]
+    fn frames_past_u32_do_not_wrap() {
+        let script = InputScript::parse("5 1 down\n4294967301 2 down").unwrap();
+        let mut keypad = Keypad::new();
+        script.apply(5, &mut keypad);
+        assert!(keypad.is_pressed(1) && !keypad.is_pressed(2));
+        let mut keypad = Keypad::new();
+        script.apply(4294967301, &mut keypad);
+        assert!(!keypad.is_pressed(1) && keypad.is_pressed(2));
+    }
+}
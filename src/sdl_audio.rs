@@ -0,0 +1,69 @@
+extern crate sdl2;
+
+use options::SAMPLE_RATE;
+
+use rip8::{Audio, Oscillator, Pattern, Tone};
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+struct Buzzer {
+    oscillator: Oscillator,
+}
+
+impl AudioCallback for Buzzer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.oscillator.fill(out);
+    }
+}
+
+/// Buzzer played through the default SDL audio device.
+pub struct SdlAudio {
+    device: AudioDevice<Buzzer>,
+    playing: bool,
+    pattern: Option<Pattern>,
+}
+
+impl SdlAudio {
+    pub fn new(sdl_context: &sdl2::Sdl, tone: Tone) -> Result<SdlAudio, String> {
+        let audio_subsystem = sdl_context.audio()?;
+        let desired_spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem.open_playback(None, &desired_spec, |spec| {
+            Buzzer {
+                oscillator: Oscillator::new(tone, spec.freq as u32),
+            }
+        })?;
+
+        Ok(SdlAudio {
+            device,
+            playing: false,
+            pattern: None,
+        })
+    }
+}
+
+impl Audio for SdlAudio {
+    fn set_playing(&mut self, playing: bool) {
+        if playing == self.playing {
+            return;
+        }
+        if playing {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+        self.playing = playing;
+    }
+
+    fn set_pattern(&mut self, pattern: Option<Pattern>) {
+        if pattern != self.pattern {
+            self.device.lock().oscillator.set_pattern(pattern);
+            self.pattern = pattern;
+        }
+    }
+}
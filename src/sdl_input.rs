@@ -0,0 +1,165 @@
+extern crate sdl2;
+
+use rip8::{Input, Keypad};
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{self, Keycode};
+use sdl2::mouse::MouseButton;
+
+/// Frontend actions bound to keys outside the keypad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    /// F1-F4, save to the slot 1-4.
+    SaveState(u8),
+    /// Shift+F1-F4, load from the slot 1-4.
+    LoadState(u8),
+    /// F5, show or hide the debug panel.
+    ToggleOverlay,
+    /// F6, open or close the memory viewer.
+    ToggleMemoryViewer,
+    /// Up/Down and PageUp/PageDown or the mouse wheel, rows to scroll the memory viewer.
+    Scroll(i32),
+    /// Left/Right, bytes to move the sprite view by.
+    MoveSprite(i32),
+    /// Home, the sprite view follows I again.
+    FollowIndex,
+    /// A left click into a window.
+    Click { window_id: u32, x: i32, y: i32 },
+    /// The close button of a window, `quit` is only set once the last window closes.
+    CloseWindow(u32),
+}
+
+/// Keypad input read from the SDL event queue.
+///
+/// The keyboard is mapped onto the keypad values as follows:
+///
+/// ```text
+/// 1 2 3 4      0 1 2 3
+/// Q W E R  ->  4 5 6 7
+/// A S D F      8 9 A B
+/// Y X C V      C D E F
+/// ```
+pub struct SdlInput {
+    event_pump: sdl2::EventPump,
+    pub keypad: Keypad,
+    pub quit: bool,
+    /// Hotkeys pressed since the last poll.
+    pub hotkeys: Vec<Hotkey>,
+    /// True while Backspace is held.
+    pub rewind: bool,
+}
+
+impl SdlInput {
+    pub fn new(event_pump: sdl2::EventPump) -> SdlInput {
+        SdlInput {
+            event_pump,
+            keypad: Keypad::new(),
+            quit: false,
+            hotkeys: Vec::new(),
+            rewind: false,
+        }
+    }
+
+    /// Drains the SDL event queue and updates the key state.
+    pub fn poll(&mut self) {
+        self.hotkeys.clear();
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
+            self.handle_event(event);
+        }
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Quit{..} | Event::KeyDown {keycode: Some(Keycode::Escape), ..} => {
+                self.quit = true;
+            }
+            Event::KeyDown {keycode: Some(keycode), keymod, repeat: false, ..} if slot(keycode).is_some() => {
+                let slot = slot(keycode).unwrap();
+                if keymod.intersects(keyboard::LSHIFTMOD | keyboard::RSHIFTMOD) {
+                    self.hotkeys.push(Hotkey::LoadState(slot));
+                } else {
+                    self.hotkeys.push(Hotkey::SaveState(slot));
+                }
+            }
+            Event::KeyDown {keycode: Some(Keycode::F5), repeat: false, ..} => self.hotkeys.push(Hotkey::ToggleOverlay),
+            Event::KeyDown {keycode: Some(Keycode::F6), repeat: false, ..} => self.hotkeys.push(Hotkey::ToggleMemoryViewer),
+            Event::KeyDown {keycode: Some(keycode), ..} if navigation(keycode).is_some() => {
+                self.hotkeys.push(navigation(keycode).unwrap());
+            }
+            Event::MouseWheel {y, ..} => self.hotkeys.push(Hotkey::Scroll(-2 * y)),
+            Event::MouseButtonDown {window_id, mouse_btn: MouseButton::Left, x, y, ..} => {
+                self.hotkeys.push(Hotkey::Click { window_id, x, y });
+            }
+            Event::Window {window_id, win_event: WindowEvent::Close, ..} => self.hotkeys.push(Hotkey::CloseWindow(window_id)),
+            Event::KeyDown {keycode: Some(Keycode::Backspace), ..} => self.rewind = true,
+            Event::KeyUp {keycode: Some(Keycode::Backspace), ..} => self.rewind = false,
+            Event::KeyDown {keycode: Some(keycode), ..} => {
+                if let Some(key) = map_keycode(keycode) {
+                    self.keypad.press(key);
+                }
+            }
+            Event::KeyUp {keycode: Some(keycode), ..} => {
+                if let Some(key) = map_keycode(keycode) {
+                    self.keypad.release(key);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Input for SdlInput {
+    fn is_pressed(&self, key: u8) -> bool {
+        self.keypad.is_pressed(key)
+    }
+
+}
+
+/// Maps F1-F4 to the save state slots 1-4.
+fn slot(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        _ => None
+    }
+}
+
+/// Maps the keys of the memory viewer.
+fn navigation(keycode: Keycode) -> Option<Hotkey> {
+    match keycode {
+        Keycode::Up => Some(Hotkey::Scroll(-1)),
+        Keycode::Down => Some(Hotkey::Scroll(1)),
+        Keycode::PageUp => Some(Hotkey::Scroll(-16)),
+        Keycode::PageDown => Some(Hotkey::Scroll(16)),
+        Keycode::Left => Some(Hotkey::MoveSprite(-1)),
+        Keycode::Right => Some(Hotkey::MoveSprite(1)),
+        Keycode::Home => Some(Hotkey::FollowIndex),
+        _ => None
+    }
+}
+
+/// Maps a keyboard key to its hex keypad value.
+pub fn map_keycode(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::Num1 => Some(0x00),
+        Keycode::Num2 => Some(0x01),
+        Keycode::Num3 => Some(0x02),
+        Keycode::Num4 => Some(0x03),
+        Keycode::Q => Some(0x04),
+        Keycode::W => Some(0x05),
+        Keycode::E => Some(0x06),
+        Keycode::R => Some(0x07),
+        Keycode::A => Some(0x08),
+        Keycode::S => Some(0x09),
+        Keycode::D => Some(0x0A),
+        Keycode::F => Some(0x0B),
+        Keycode::Y => Some(0x0C),
+        Keycode::X => Some(0x0D),
+        Keycode::C => Some(0x0E),
+        Keycode::V => Some(0x0F),
+        _ => None
+    }
+}
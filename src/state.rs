@@ -0,0 +1,208 @@
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+/// First bytes of every save state.
+pub const STATE_MAGIC: [u8; 4] = *b"R8ST";
+/// Version of the save state layout, bumped whenever the layout changes.
+pub const STATE_VERSION: u16 = 1;
+
+/// Errors raised while reading a save state or a movie.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the magic of the expected file type.
+    BadMagic,
+    /// The data was written by a different layout version.
+    UnsupportedVersion { found: u16, expected: u16 },
+    /// The data ended in the middle of a value.
+    Truncated,
+    /// A field holds a value the interpreter can never be in.
+    InvalidField(&'static str),
+    /// The state was saved with another kind of random number generator.
+    RngMismatch,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::BadMagic => write!(f, "unknown file type"),
+            StateError::UnsupportedVersion { found, expected } =>
+                write!(f, "unsupported format version {} (expected {})", found, expected),
+            StateError::Truncated => write!(f, "file is truncated"),
+            StateError::InvalidField(field) => write!(f, "invalid {}", field),
+            StateError::RngMismatch => write!(f, "saved with a different random number generator"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for StateError {}
+
+/// Appends little endian values to a save state or movie.
+pub(crate) struct StateWriter {
+    pub data: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Starts a save state with its magic and version header.
+    pub fn new() -> StateWriter {
+        StateWriter::with_header(STATE_MAGIC, STATE_VERSION)
+    }
+
+    pub fn with_header(magic: [u8; 4], version: u16) -> StateWriter {
+        let mut writer = StateWriter { data: Vec::new() };
+        writer.bytes(&magic);
+        writer.u16(version);
+        writer
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+/// Reads the values written by `StateWriter` back in the same order.
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the save state header and returns a reader positioned after it.
+    pub fn new(data: &'a [u8]) -> Result<StateReader<'a>, StateError> {
+        StateReader::with_header(data, STATE_MAGIC, STATE_VERSION)
+    }
+
+    pub fn with_header(data: &'a [u8], magic: [u8; 4], version: u16) -> Result<StateReader<'a>, StateError> {
+        let mut reader = StateReader { data };
+        if reader.bytes(magic.len()).ok() != Some(&magic[..]) {
+            return Err(StateError::BadMagic);
+        }
+        let found = reader.u16()?;
+        if found != version {
+            return Err(StateError::UnsupportedVersion { found, expected: version });
+        }
+        Ok(reader)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self, field: &'static str) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidField(field)),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Returns true once all data has been read.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Fails unless all data has been read.
+    pub fn finish(&self) -> Result<(), StateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::InvalidField("length"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::{Chip8, Mode};
+    use keypad::Keypad;
+    use rng::SequenceRng;
+
+    //draws a random digit, counts and runs the timers
+    const ROM: [u8; 16] = [
+        0xC0, 0x0F, 0xF0, 0x29, 0xD1, 0x25, 0x71, 0x05,
+        0x62, 0x20, 0xF2, 0x15, 0x73, 0x01, 0x12, 0x00,
+    ];
+
+    fn run(chip8: &mut Chip8, frames: usize) {
+        for _ in 0..frames {
+            chip8.run_frame(&Keypad::new(), 7).unwrap();
+        }
+    }
+
+    #[test]
+    fn loaded_state_runs_like_the_saved_machine() {
+        let mut saved = Chip8::with_mode(ROM.to_vec(), Mode::SuperChip).unwrap();
+        run(&mut saved, 3);
+        let mut keypad = Keypad::new();
+        keypad.press(0xA);
+        let state = saved.save_state(&keypad);
+
+        let mut loaded = Chip8::with_mode(Vec::new(), Mode::Chip8).unwrap();
+        assert_eq!(loaded.load_state(&state), Ok(keypad));
+        assert_eq!(loaded.save_state(&keypad), state);
+        run(&mut saved, 5);
+        run(&mut loaded, 5);
+        assert_eq!(loaded.save_state(&keypad), saved.save_state(&keypad));
+        assert!(loaded.registers()[3] > 0);
+    }
+
+    #[test]
+    fn invalid_states_are_rejected() {
+        let mut chip8 = Chip8::with_mode(ROM.to_vec(), Mode::Chip8).unwrap();
+        let state = chip8.save_state(&Keypad::new());
+        assert_eq!(chip8.load_state(b"R8MV"), Err(StateError::BadMagic));
+        assert_eq!(chip8.load_state(&state[..state.len() - 1]), Err(StateError::Truncated));
+        let mut newer = state.clone();
+        newer[4] = 2;
+        assert_eq!(chip8.load_state(&newer), Err(StateError::UnsupportedVersion { found: 2, expected: 1 }));
+        chip8.set_rng(Box::new(SequenceRng::new(vec![1])));
+        assert_eq!(chip8.load_state(&state), Err(StateError::RngMismatch));
+    }
+}
@@ -0,0 +1,474 @@
+use instruction::Instruction;
+use state::{StateError, StateReader, StateWriter};
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+use core::str::FromStr;
+
+/// First bytes of every binary trace.
+pub const TRACE_MAGIC: [u8; 4] = *b"R8TR";
+/// Version of the binary trace layout, bumped whenever the layout changes.
+pub const TRACE_VERSION: u16 = 1;
+/// Size of a step in a binary trace.
+pub const STEP_SIZE: usize = 43;
+
+/// Importance of a message, from the most to the least important.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// The machine halted.
+    Error,
+    /// An instruction failed and was skipped.
+    Warn,
+    /// Rare events, e.g. the program exited.
+    Info,
+    /// Everything else worth knowing when debugging a ROM.
+    Debug,
+}
+
+impl FromStr for Level {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Level, String> {
+        match s {
+            "error" => Ok(Level::Error),
+            "warn" => Ok(Level::Warn),
+            "info" => Ok(Level::Info),
+            "debug" => Ok(Level::Debug),
+            _ => Err(format!("unknown log level '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Level::Error => write!(f, "error"),
+            Level::Warn => write!(f, "warn"),
+            Level::Info => write!(f, "info"),
+            Level::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+/// Part of the interpreter a message is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// Instruction execution, errors and exit.
+    Cpu,
+    /// Resolution changes.
+    Display,
+    /// FX0A waiting for keys.
+    Input,
+    /// XO-CHIP audio patterns and pitch.
+    Audio,
+    /// 0NNN machine code routines.
+    MachineCode,
+}
+
+/// All targets, in the order of their levels in `Tracing`.
+pub const TARGETS: [Target; 5] = [Target::Cpu, Target::Display, Target::Input, Target::Audio, Target::MachineCode];
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Target, String> {
+        match s {
+            "cpu" => Ok(Target::Cpu),
+            "display" => Ok(Target::Display),
+            "input" => Ok(Target::Input),
+            "audio" => Ok(Target::Audio),
+            "machine-code" => Ok(Target::MachineCode),
+            _ => Err(format!("unknown log target '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Target::Cpu => write!(f, "cpu"),
+            Target::Display => write!(f, "display"),
+            Target::Input => write!(f, "input"),
+            Target::Audio => write!(f, "audio"),
+            Target::MachineCode => write!(f, "machine-code"),
+        }
+    }
+}
+
+/// What the interpreter records, see `Chip8::take_records`.
+///
+/// Nothing is recorded by default. A frontend turning anything on has to
+/// take the records regularly, they pile up otherwise.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tracing {
+    //least important level recorded for each target of TARGETS, None for none
+    levels: [Option<Level>; 5],
+    /// Records a `Step` before every executed instruction.
+    pub steps: bool,
+    /// Only steps at these addresses are recorded.
+    pub addresses: Option<Range<usize>>,
+    /// Only steps in these cycles are recorded, see `Chip8::cycles`.
+    pub cycles: Option<Range<u64>>,
+}
+
+impl Tracing {
+    /// Records nothing.
+    pub fn new() -> Tracing {
+        Tracing::default()
+    }
+
+    /// Records the messages of `target` up to `level`, None records none.
+    pub fn set_level(&mut self, target: Target, level: Option<Level>) {
+        self.levels[target as usize] = level;
+    }
+
+    /// Sets the levels from a list like `warn,input=debug,audio=off`.
+    ///
+    /// A level without a target applies to all targets.
+    pub fn set_levels(&mut self, spec: &str) -> Result<(), String> {
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (targets, level) = match item.find('=') {
+                Some(equals) => (vec![item[..equals].parse()?], &item[equals + 1..]),
+                None => (TARGETS.to_vec(), item),
+            };
+            let level = match level {
+                "off" => None,
+                level => Some(level.parse()?),
+            };
+            for target in targets {
+                self.set_level(target, level);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if messages of `target` at `level` are recorded.
+    pub fn is_enabled(&self, target: Target, level: Level) -> bool {
+        self.levels[target as usize].is_some_and(|least| level <= least)
+    }
+
+    /// Returns true if the instruction at `pc` in `cycle` is recorded as a step.
+    pub fn traces(&self, cycle: u64, pc: u16) -> bool {
+        self.steps
+            && self.addresses.as_ref().is_none_or(|addresses| addresses.contains(&(pc as usize)))
+            && self.cycles.as_ref().is_none_or(|cycles| cycles.contains(&cycle))
+    }
+}
+
+/// A message of the interpreter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    pub cycle: u64,
+    pub level: Level,
+    pub target: Target,
+    pub text: String,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}: {}", self.cycle, self.level, self.target, self.text)
+    }
+}
+
+/// The machine right before an instruction is executed.
+///
+/// rip8 records every field, the optional ones can be missing from text traces
+/// of other emulators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub cycle: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    /// The address of F000 NNNN, 0 for all other instructions.
+    pub operand: u16,
+    pub registers: Option<[u8; 16]>,
+    pub index: Option<u16>,
+    pub sp: Option<u8>,
+    pub delay_timer: Option<u8>,
+    pub sound_timer: Option<u8>,
+    /// `rom_hash` of `Chip8::screen`.
+    pub screen: Option<u64>,
+}
+
+impl Step {
+    pub fn instruction(&self) -> Instruction {
+        let [high, low] = self.opcode.to_be_bytes();
+        let [operand_high, operand_low] = self.operand.to_be_bytes();
+        Instruction::decode_at(&[high, low, operand_high, operand_low], 0).unwrap_or(Instruction::Invalid(self.opcode))
+    }
+
+    /// Encodes the step as a record of a binary trace, `STEP_SIZE` bytes.
+    /// Missing fields are written as zero.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter { data: Vec::with_capacity(STEP_SIZE) };
+        writer.u64(self.cycle);
+        writer.u16(self.pc);
+        writer.u16(self.opcode);
+        writer.u16(self.operand);
+        writer.bytes(&self.registers.unwrap_or([0; 16]));
+        writer.u16(self.index.unwrap_or(0));
+        writer.u8(self.sp.unwrap_or(0));
+        writer.u8(self.delay_timer.unwrap_or(0));
+        writer.u8(self.sound_timer.unwrap_or(0));
+        writer.u64(self.screen.unwrap_or(0));
+        writer.data
+    }
+
+    fn read(reader: &mut StateReader) -> Result<Step, StateError> {
+        let cycle = reader.u64()?;
+        let pc = reader.u16()?;
+        let opcode = reader.u16()?;
+        let operand = reader.u16()?;
+        let mut registers = [0; 16];
+        registers.copy_from_slice(reader.bytes(16)?);
+        Ok(Step {
+            cycle,
+            pc,
+            opcode,
+            operand,
+            registers: Some(registers),
+            index: Some(reader.u16()?),
+            sp: Some(reader.u8()?),
+            delay_timer: Some(reader.u8()?),
+            sound_timer: Some(reader.u8()?),
+            screen: Some(reader.u64()?),
+        })
+    }
+}
+
+/// One line of a text trace: cycle, pc, opcode (followed by the address of
+/// F000 NNNN), V0 to VF, I, SP, the timers, the screen hash and the disassembly, e.g.
+///
+/// ```text
+///        4 0208 7102 V 01000000000000000000000000000000 I 0000 SP 1 DT 00 ST 00 FB 28C31CF8DF2EC325  v1 += 0x02
+/// ```
+///
+/// Missing fields are left out.
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:8} {:04X} {:04X}", self.cycle, self.pc, self.opcode)?;
+        if let Instruction::LongIndex(_) = self.instruction() {
+            write!(f, "{:04X}", self.operand)?;
+        }
+        if let Some(registers) = self.registers {
+            write!(f, " V ")?;
+            for register in registers.iter() {
+                write!(f, "{:02X}", register)?;
+            }
+        }
+        if let Some(index) = self.index {
+            write!(f, " I {:04X}", index)?;
+        }
+        if let Some(sp) = self.sp {
+            write!(f, " SP {:X}", sp)?;
+        }
+        if let Some(delay_timer) = self.delay_timer {
+            write!(f, " DT {:02X}", delay_timer)?;
+        }
+        if let Some(sound_timer) = self.sound_timer {
+            write!(f, " ST {:02X}", sound_timer)?;
+        }
+        if let Some(screen) = self.screen {
+            write!(f, " FB {:016X}", screen)?;
+        }
+        write!(f, "  {}", self.instruction())
+    }
+}
+
+/// Parses a line of a text trace.
+///
+/// Only the cycle, pc and opcode are required, missing fields are `None` and
+/// the disassembly is ignored. This keeps traces of other emulators easy to
+/// convert.
+impl FromStr for Step {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Step, String> {
+        //the disassembly is separated by two spaces
+        let line = line.trim_start().split("  ").next().unwrap_or("");
+        let mut fields = line.split_whitespace();
+        let mut next = |name: &str| fields.next().ok_or_else(|| format!("missing {}", name));
+        let hex = |name: &str, value: &str| u64::from_str_radix(value, 16).map_err(|_| format!("invalid {} '{}'", name, value));
+        let cycle = next("cycle")?;
+        let pc = hex("pc", next("pc")?)? as u16;
+        //F000 NNNN has its address appended
+        let opcode = next("opcode")?;
+        let (opcode, operand) = match opcode.len() {
+            8 => {
+                let long = hex("opcode", opcode)?;
+                ((long >> 16) as u16, long as u16)
+            }
+            _ => (hex("opcode", opcode)? as u16, 0),
+        };
+        let mut step = Step {
+            cycle: cycle.parse().map_err(|_| format!("invalid cycle '{}'", cycle))?,
+            pc,
+            opcode,
+            operand,
+            registers: None,
+            index: None,
+            sp: None,
+            delay_timer: None,
+            sound_timer: None,
+            screen: None,
+        };
+        while let Some(name) = fields.next() {
+            let value = fields.next().ok_or_else(|| format!("missing value of {}", name))?;
+            match name {
+                "V" => {
+                    if value.len() != 32 || !value.is_ascii() {
+                        return Err(format!("invalid registers '{}'", value));
+                    }
+                    let mut registers = [0; 16];
+                    for (x, register) in registers.iter_mut().enumerate() {
+                        *register = hex("registers", &value[2 * x..2 * x + 2])? as u8;
+                    }
+                    step.registers = Some(registers);
+                }
+                "I" => step.index = Some(hex("I", value)? as u16),
+                "SP" => step.sp = Some(hex("SP", value)? as u8),
+                "DT" => step.delay_timer = Some(hex("DT", value)? as u8),
+                "ST" => step.sound_timer = Some(hex("ST", value)? as u8),
+                "FB" => step.screen = Some(hex("FB", value)?),
+                _ => return Err(format!("unknown field '{}'", name)),
+            }
+        }
+        Ok(step)
+    }
+}
+
+/// Reads the steps of a trace in either format, binary traces start with `TRACE_MAGIC`.
+pub fn read_trace(data: &[u8]) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    if data.starts_with(&TRACE_MAGIC) {
+        let mut reader = StateReader::with_header(data, TRACE_MAGIC, TRACE_VERSION).map_err(|e| e.to_string())?;
+        while !reader.is_empty() {
+            steps.push(Step::read(&mut reader).map_err(|e| format!("step {}: {}", steps.len() + 1, e))?);
+        }
+    } else {
+        let text = core::str::from_utf8(data).map_err(|_| String::from("neither a binary nor a text trace"))?;
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            steps.push(line.parse().map_err(|e| format!("line {}: {}", number + 1, e))?);
+        }
+    }
+    Ok(steps)
+}
+
+/// Something recorded by the interpreter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Record {
+    Message(Message),
+    Step(Step),
+}
+
+/// How steps are written to a trace file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per step, see the `Display` of `Step`.
+    Text,
+    /// The `TRACE_MAGIC` header followed by `Step::to_bytes` of every step.
+    Binary,
+}
+
+impl TraceFormat {
+    /// Bytes at the start of a trace file.
+    pub fn header(&self) -> Vec<u8> {
+        match *self {
+            TraceFormat::Text => Vec::new(),
+            TraceFormat::Binary => StateWriter::with_header(TRACE_MAGIC, TRACE_VERSION).data,
+        }
+    }
+
+    /// Encodes `step` for a trace file.
+    pub fn encode(&self, step: &Step) -> Vec<u8> {
+        match *self {
+            TraceFormat::Text => format!("{}\n", step).into_bytes(),
+            TraceFormat::Binary => step.to_bytes(),
+        }
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TraceFormat, String> {
+        match s {
+            "text" => Ok(TraceFormat::Text),
+            "binary" => Ok(TraceFormat::Binary),
+            _ => Err(format!("unknown trace format '{}'", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::{Chip8, Mode};
+    use keypad::Keypad;
+
+    //steps of a short XO-CHIP program with F000 NNNN and a draw, which waits for the next frame
+    fn steps() -> Vec<Step> {
+        let rom = vec![0x60, 0x05, 0xF0, 0x00, 0x12, 0x34, 0xA0, 0x00, 0xD0, 0x05, 0x12, 0x00];
+        let mut chip8 = Chip8::with_mode(rom, Mode::XoChip).unwrap();
+        chip8.tracing.steps = true;
+        chip8.run_frame(&Keypad::new(), 6).unwrap();
+        chip8.take_records().into_iter()
+            .filter_map(|record| match record {
+                Record::Step(step) => Some(step),
+                Record::Message(_) => None,
+            })
+            .collect()
+    }
+
+    fn write(format: TraceFormat, steps: &[Step]) -> Vec<u8> {
+        let mut data = format.header();
+        for step in steps {
+            data.extend(format.encode(step));
+        }
+        data
+    }
+
+    #[test]
+    fn binary_trace_round_trips() {
+        let steps = steps();
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps[1].instruction(), Instruction::LongIndex(0x1234));
+        assert!(steps.iter().all(|step| step.to_bytes().len() == STEP_SIZE));
+        let data = write(TraceFormat::Binary, &steps);
+        assert_eq!(data.len(), 6 + steps.len() * STEP_SIZE);
+        assert_eq!(read_trace(&data), Ok(steps));
+    }
+
+    #[test]
+    fn text_trace_round_trips() {
+        let steps = steps();
+        assert_eq!(read_trace(&write(TraceFormat::Text, &steps)), Ok(steps));
+    }
+
+    #[test]
+    fn broken_traces_are_rejected() {
+        let data = write(TraceFormat::Binary, &steps());
+        assert_eq!(read_trace(&data[..data.len() - 1]), Err(String::from("step 4: file is truncated")));
+        let mut newer = data.clone();
+        newer[4] = 2;
+        assert!(read_trace(&newer).is_err());
+        assert_eq!(read_trace(b"1 0200"), Err(String::from("line 1: missing opcode")));
+        assert_eq!(read_trace(b"1 0200 6005 V 00"), Err(String::from("line 1: invalid registers '00'")));
+    }
+
+    #[test]
+    fn steps_are_limited_to_the_ranges() {
+        let mut tracing = Tracing::new();
+        assert!(!tracing.traces(1, 0x200));
+        tracing.steps = true;
+        tracing.addresses = Some(0x200..0x300);
+        tracing.cycles = Some(10..20);
+        assert!(tracing.traces(10, 0x2FF));
+        assert!(!tracing.traces(20, 0x200) && !tracing.traces(10, 0x300));
+    }
+}
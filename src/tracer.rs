@@ -0,0 +1,58 @@
+use options::Options;
+
+use rip8::{Chip8, Record, TraceFormat};
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, BufWriter};
+
+/// Takes the records of the interpreter: messages go to stderr, steps to the trace file.
+pub struct Tracer {
+    file: Option<(BufWriter<File>, TraceFormat)>,
+    path: String,
+}
+
+impl Tracer {
+    /// Creates the trace file of `--trace`, if any.
+    pub fn new(options: &Options) -> Result<Tracer, String> {
+        let path = match options.trace {
+            Some(ref path) => path.clone(),
+            None => return Ok(Tracer { file: None, path: String::new() }),
+        };
+        let format = options.trace_format;
+        let file = File::create(&path)
+            .and_then(|file| {
+                let mut file = BufWriter::new(file);
+                file.write_all(&format.header())?;
+                Ok(file)
+            })
+            .map_err(|e| write_error(&path, e))?;
+        Ok(Tracer { file: Some((file, format)), path })
+    }
+
+    /// Writes the records of `chip8` since the last call, meant to be called after every frame.
+    pub fn write(&mut self, chip8: &mut Chip8) -> Result<(), String> {
+        let path = &self.path;
+        for record in chip8.take_records() {
+            match record {
+                Record::Message(message) => eprintln!("{}", message),
+                Record::Step(step) => if let Some((ref mut file, format)) = self.file {
+                    file.write_all(&format.encode(&step)).map_err(|e| write_error(path, e))?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes out the buffered steps.
+    pub fn finish(&mut self) -> Result<(), String> {
+        match self.file {
+            Some((ref mut file, _)) => file.flush().map_err(|e| write_error(&self.path, e)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_error(path: &str, e: io::Error) -> String {
+    format!("Error while writing {}: {}", path, e)
+}